@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use avian3d::prelude::LayerMask;
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::{capital_ships::CapitalShip, health::Health, simulation::every, Team, TeamTarget};
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<Alliances>();
+    app.init_resource::<Alliances>();
+    app.add_systems(
+        FixedUpdate,
+        assign_team_targets.run_if(every(Duration::from_secs(1))),
+    );
+}
+
+/// Which teams fight on the same side. Teams not listed together are hostile, so the default
+/// is a free-for-all.
+#[derive(Resource, Reflect, Deserialize, Debug, Clone, Default)]
+#[reflect(Resource)]
+#[serde(transparent)]
+pub struct Alliances {
+    pub groups: Vec<Vec<Team>>,
+}
+
+impl Alliances {
+    pub fn are_allied(&self, a: Team, b: Team) -> bool {
+        a == b
+            || self
+                .groups
+                .iter()
+                .any(|group| group.contains(&a) && group.contains(&b))
+    }
+
+    pub fn are_hostile(&self, a: Team, b: Team) -> bool {
+        !self.are_allied(a, b)
+    }
+
+    /// Layers of every team allied with `team`, including itself.
+    pub fn allied_layers(&self, team: Team) -> LayerMask {
+        Self::layers(
+            Team::ALL
+                .into_iter()
+                .filter(|&other| self.are_allied(team, other)),
+        )
+    }
+
+    /// Layers of every team hostile to `team`.
+    pub fn hostile_layers(&self, team: Team) -> LayerMask {
+        Self::layers(
+            Team::ALL
+                .into_iter()
+                .filter(|&other| self.are_hostile(team, other)),
+        )
+    }
+
+    fn layers(teams: impl Iterator<Item = Team>) -> LayerMask {
+        teams.fold(LayerMask::NONE, |mask, team| {
+            mask | LayerMask::from(team.layer())
+        })
+    }
+}
+
+/// A [`TeamTarget`] that is kept on the nearest hostile capital ship.
+#[derive(Component)]
+#[require(TeamTarget)]
+struct AutoTeamTarget;
+
+fn assign_team_targets(
+    mut commands: Commands,
+    alliances: Res<Alliances>,
+    capital_ships: Query<(&GlobalTransform, &Team, &Health), With<CapitalShip>>,
+    mut targets: Query<(Entity, &mut Transform, &TeamTarget), With<AutoTeamTarget>>,
+) {
+    let capital_ships: Vec<(Vec3, Team)> = capital_ships
+        .iter()
+        .filter(|(_, _, health)| !health.is_dead())
+        .map(|(transform, team, _)| (transform.translation(), *team))
+        .collect();
+
+    // The closest hostile capital ship to any of the team's own capital ships.
+    let mut wanted: HashMap<Team, (f32, Vec3)> = HashMap::new();
+    for &(position, team) in capital_ships.iter() {
+        for &(target, _) in capital_ships
+            .iter()
+            .filter(|(_, other)| alliances.are_hostile(team, *other))
+        {
+            let distance = target.distance_squared(position);
+            if wanted
+                .get(&team)
+                .is_none_or(|(closest, _)| distance < *closest)
+            {
+                wanted.insert(team, (distance, target));
+            }
+        }
+    }
+
+    for (entity, mut transform, TeamTarget(team)) in targets.iter_mut() {
+        match wanted.remove(team) {
+            Some((_, target)) => transform.translation = target,
+            None => commands.entity(entity).despawn(),
+        }
+    }
+    for (team, (_, target)) in wanted {
+        commands.spawn((
+            AutoTeamTarget,
+            TeamTarget(team),
+            Transform::from_translation(target),
+        ));
+    }
+}
@@ -0,0 +1,222 @@
+//! Procedurally generated asteroid fields for fighters to dodge and hide behind.
+use std::ops::Range;
+
+use avian3d::prelude::{AngularVelocity, LinearVelocity, RigidBody};
+use bevy::{ecs::system::EntityCommand, math::vec3, prelude::*};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::{
+    health::{DamageSystems, Health, ShipDestroyed},
+    simulation::SimRng,
+    Obstacle,
+};
+
+/// Health per unit of asteroid radius.
+const HEALTH_PER_RADIUS: f32 = 2.0;
+/// Fragments an asteroid breaks into.
+const FRAGMENTS: usize = 3;
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<Asteroid>();
+    app.add_systems(Startup, setup);
+    app.add_systems(FixedUpdate, split_asteroids.after(DamageSystems));
+}
+
+#[derive(Component, Reflect, Debug, Clone)]
+#[require(Obstacle)]
+pub struct Asteroid {
+    pub radius: f32,
+    /// Destructible asteroids smaller than this crumble to dust instead of splitting.
+    pub min_split_radius: f32,
+}
+
+#[derive(Resource, Reflect)]
+struct AsteroidAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup(
+    mut commands: Commands,
+    meshes: Option<ResMut<Assets<Mesh>>>,
+    materials: Option<ResMut<Assets<StandardMaterial>>>,
+) {
+    // Headless runs have no renderer, asteroids are spawned without meshes.
+    let (Some(mut meshes), Some(mut materials)) = (meshes, materials) else {
+        return;
+    };
+    let mesh = meshes.add(
+        Sphere::new(1.0)
+            .mesh()
+            .ico(2)
+            .expect("ico sphere subdivisions should be valid"),
+    );
+    let material = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.35, 0.32, 0.3),
+        perceptual_roughness: 1.0,
+        ..default()
+    });
+    commands.insert_resource(AsteroidAssets { mesh, material });
+}
+
+/// A box of randomly placed asteroids, the same seed always produces the same field.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AsteroidFieldSpec {
+    pub seed: u64,
+    pub center: [f32; 3],
+    /// Half the field's size along each axis.
+    pub half_extents: [f32; 3],
+    pub count: usize,
+    pub min_radius: f32,
+    pub max_radius: f32,
+    /// Fastest an asteroid drifts, in units per second.
+    pub max_drift: f32,
+    /// Fastest an asteroid tumbles, in degrees per second.
+    pub max_spin: f32,
+    /// Whether lasers can break asteroids apart.
+    pub destructible: bool,
+    pub min_split_radius: f32,
+}
+
+impl Default for AsteroidFieldSpec {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            center: [0.0; 3],
+            half_extents: [40.0, 10.0, 40.0],
+            count: 40,
+            min_radius: 1.0,
+            max_radius: 4.0,
+            max_drift: 0.5,
+            max_spin: 10.0,
+            destructible: true,
+            min_split_radius: 1.0,
+        }
+    }
+}
+
+pub struct SpawnAsteroidField(pub AsteroidFieldSpec);
+
+impl Command for SpawnAsteroidField {
+    fn apply(self, world: &mut World) {
+        let spec = self.0;
+        let mut rng = StdRng::seed_from_u64(spec.seed);
+        let center = Vec3::from(spec.center);
+        let half_extents = Vec3::from(spec.half_extents);
+        for _ in 0..spec.count {
+            let offset = vec3(
+                rng.gen_range(-1.0..=1.0),
+                rng.gen_range(-1.0..=1.0),
+                rng.gen_range(-1.0..=1.0),
+            ) * half_extents;
+            SpawnAsteroid {
+                position: center + offset,
+                radius: rng.gen_range(spec.min_radius..=spec.max_radius.max(spec.min_radius)),
+                velocity: random_vector(&mut rng, 0.0..spec.max_drift.max(f32::EPSILON)),
+                spin: random_vector(&mut rng, 0.0..spec.max_spin.to_radians().max(f32::EPSILON)),
+                destructible: spec.destructible,
+                min_split_radius: spec.min_split_radius,
+                shape_seed: rng.gen(),
+            }
+            .apply(world.spawn_empty().id(), world);
+        }
+    }
+}
+
+/// Turns an empty entity into an asteroid, e.g. `commands.spawn_empty().queue(..)`.
+pub struct SpawnAsteroid {
+    pub position: Vec3,
+    pub radius: f32,
+    pub velocity: Vec3,
+    pub spin: Vec3,
+    pub destructible: bool,
+    pub min_split_radius: f32,
+    /// Drives the lumpy shape of the mesh.
+    pub shape_seed: u64,
+}
+
+impl EntityCommand for SpawnAsteroid {
+    fn apply(self, entity: Entity, world: &mut World) {
+        let visuals = world.get_resource::<AsteroidAssets>().map(|assets| {
+            (
+                Mesh3d(assets.mesh.clone()),
+                MeshMaterial3d(assets.material.clone()),
+            )
+        });
+        let mut asteroid = world.entity_mut(entity);
+        asteroid.insert((
+            Asteroid {
+                radius: self.radius,
+                min_split_radius: self.min_split_radius,
+            },
+            Obstacle {
+                radius: self.radius,
+            },
+            // Kinematic so asteroids drift on their own but don't get shoved around by fighters.
+            RigidBody::Kinematic,
+            Transform::from_translation(self.position),
+            LinearVelocity(self.velocity),
+            AngularVelocity(self.spin),
+        ));
+        if self.destructible {
+            asteroid.insert(Health::new(self.radius * HEALTH_PER_RADIUS));
+        }
+        if let Some(visuals) = visuals {
+            // Squash the sphere a little so no two asteroids look alike. The mesh lives on a child
+            // so the scale doesn't stretch the collider.
+            let mut rng = StdRng::seed_from_u64(self.shape_seed);
+            let scale = vec3(
+                rng.gen_range(0.8..1.1),
+                rng.gen_range(0.7..1.0),
+                rng.gen_range(0.85..1.15),
+            ) * self.radius;
+            asteroid.with_child((visuals, Transform::from_scale(scale)));
+        }
+    }
+}
+
+/// Breaks destroyed asteroids into smaller fragments flying apart from each other.
+fn split_asteroids(
+    mut commands: Commands,
+    mut destroyed: EventReader<ShipDestroyed>,
+    asteroids: Query<(&Asteroid, &LinearVelocity)>,
+    mut rng: ResMut<SimRng>,
+) {
+    for event in destroyed.read() {
+        let Ok((asteroid, velocity)) = asteroids.get(event.entity) else {
+            continue;
+        };
+        let radius = asteroid.radius * 0.55;
+        if radius < asteroid.min_split_radius {
+            continue;
+        }
+        for _ in 0..FRAGMENTS {
+            let direction = random_direction(&mut rng.0);
+            commands.spawn_empty().queue(SpawnAsteroid {
+                position: event.position + direction * radius,
+                radius,
+                velocity: velocity.0 + direction * rng.gen_range(0.5..2.0),
+                spin: random_vector(&mut rng.0, 0.0..1.0),
+                destructible: true,
+                min_split_radius: asteroid.min_split_radius,
+                shape_seed: rng.gen(),
+            });
+        }
+    }
+}
+
+fn random_direction(rng: &mut impl Rng) -> Vec3 {
+    vec3(
+        rng.gen_range(-1.0..=1.0),
+        rng.gen_range(-1.0..=1.0),
+        rng.gen_range(-1.0..=1.0),
+    )
+    .normalize_or(Vec3::X)
+}
+
+/// A vector pointing in a random direction with a length in the given range.
+fn random_vector(rng: &mut impl Rng, length: Range<f32>) -> Vec3 {
+    random_direction(rng) * rng.gen_range(length)
+}
@@ -0,0 +1,435 @@
+//! Camera modes: free-fly, orbiting the selection, an automatic director that cuts between
+//! the fiercest fighting, and a chase view behind the selected ship.
+//!
+//! `1` free-fly: WASD to move, Q/E down and up, shift to speed up, hold the right mouse button to
+//! look around. `2` orbit: right mouse button to swing around, scroll to zoom. `3` director.
+//! `4` chase. Left click a fighter to chase it, or Tab (shift-Tab backwards) through the fighters.
+//! Left clicking a capital ship orbits it.
+use std::collections::VecDeque;
+
+use bevy::{
+    input::mouse::{AccumulatedMouseMotion, AccumulatedMouseScroll},
+    prelude::*,
+};
+use rand::{thread_rng, Rng};
+
+use crate::{
+    capital_ships::CapitalShip,
+    health::{DamageEvent, ShipDestroyed},
+    Ship, Team,
+};
+
+/// Seconds the director holds a shot before looking for a new one.
+const DIRECTOR_SHOT: f64 = 6.0;
+/// Seconds that fighting counts towards picking a shot.
+const ACTION_MEMORY: f64 = 5.0;
+/// Distance within which fighting counts as the same hot spot.
+const HOT_SPOT_RADIUS: f32 = 25.0;
+/// How close to a ship on screen, in pixels, a click has to be to pick it.
+const PICK_RADIUS: f32 = 24.0;
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<CameraRig>();
+    app.register_type::<CameraMode>();
+    app.init_resource::<Selection>();
+    app.init_resource::<Action>();
+    app.add_systems(
+        Update,
+        (
+            switch_camera_mode,
+            (pick_ship, cycle_ships, hand_off_selection),
+            (free_fly, orbit, direct, chase),
+            ease_camera,
+        )
+            .chain(),
+    );
+    app.add_systems(Update, (remember_damage, remember_kills));
+}
+
+/// The entity the player is looking at, orbited by [`CameraMode::Orbit`] and followed by
+/// [`CameraMode::Chase`].
+#[derive(Resource, Debug, Default, Clone, Copy)]
+pub struct Selection(pub Option<Entity>);
+
+#[derive(Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    FreeFly,
+    #[default]
+    Orbit,
+    Director,
+    Chase,
+}
+
+/// Drives the camera it's on. Each mode moves `goal`, the camera eases towards it so switching
+/// modes and director cuts glide rather than jump.
+#[derive(Component, Reflect, Debug, Clone)]
+pub struct CameraRig {
+    pub mode: CameraMode,
+    /// Closest and furthest the camera orbits from its focus.
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Free-fly speed in units per second.
+    pub fly_speed: f32,
+    /// Radians turned per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// How quickly the camera catches up with its goal, higher is snappier.
+    pub smoothing: f32,
+    /// Where the chase camera sits, in the chased ship's local space.
+    pub chase_offset: Vec3,
+    /// Smoothing while chasing, kept high so the ship stays framed through hard turns.
+    pub chase_smoothing: f32,
+    goal: Transform,
+    /// The point orbited around.
+    focus: Vec3,
+    distance: f32,
+    yaw: f32,
+    pitch: f32,
+    /// When the director cuts to its next shot.
+    next_cut: f64,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::default(),
+            min_distance: 5.0,
+            max_distance: 400.0,
+            fly_speed: 40.0,
+            sensitivity: 0.003,
+            smoothing: 4.0,
+            chase_offset: Vec3::new(0.0, 2.5, 9.0),
+            chase_smoothing: 8.0,
+            goal: Transform::default(),
+            focus: Vec3::ZERO,
+            distance: 100.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            next_cut: 0.0,
+        }
+    }
+}
+
+impl CameraRig {
+    /// Moves the camera to `position` looking at `focus`, ready to orbit it.
+    pub fn place(&mut self, position: Vec3, focus: Vec3) {
+        let offset = position - focus;
+        self.focus = focus;
+        self.distance = offset.length().clamp(self.min_distance, self.max_distance);
+        self.yaw = offset.x.atan2(offset.z);
+        self.pitch = (offset.y / offset.length().max(f32::EPSILON)).asin();
+        self.goal = Transform::from_translation(position).looking_at(focus, Vec3::Y);
+    }
+
+    fn orbit_goal(&self) -> Transform {
+        let offset = Quat::from_euler(EulerRot::YXZ, self.yaw, -self.pitch, 0.0)
+            * Vec3::new(0.0, 0.0, self.distance);
+        Transform::from_translation(self.focus + offset).looking_at(self.focus, Vec3::Y)
+    }
+
+    fn chase_goal(&self, target: &GlobalTransform) -> Transform {
+        let look_at = target.translation() + target.forward() * 10.0;
+        Transform::from_translation(target.transform_point(self.chase_offset))
+            .looking_at(look_at, target.up())
+    }
+}
+
+fn switch_camera_mode(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut rigs: Query<(&mut CameraRig, &Transform)>,
+) {
+    let mode = if keys.just_pressed(KeyCode::Digit1) {
+        CameraMode::FreeFly
+    } else if keys.just_pressed(KeyCode::Digit2) {
+        CameraMode::Orbit
+    } else if keys.just_pressed(KeyCode::Digit3) {
+        CameraMode::Director
+    } else if keys.just_pressed(KeyCode::Digit4) {
+        CameraMode::Chase
+    } else {
+        return;
+    };
+    for (mut rig, transform) in rigs.iter_mut() {
+        if rig.mode == mode {
+            continue;
+        }
+        // Pick up from wherever the camera is right now.
+        let focus = transform.translation + transform.forward() * rig.distance;
+        rig.place(transform.translation, focus);
+        rig.next_cut = 0.0;
+        rig.mode = mode;
+    }
+}
+
+fn free_fly(
+    mut rigs: Query<&mut CameraRig>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    time: Res<Time<Real>>,
+) {
+    for mut rig in rigs
+        .iter_mut()
+        .filter(|rig| rig.mode == CameraMode::FreeFly)
+    {
+        if mouse_buttons.pressed(MouseButton::Right) {
+            let (yaw, pitch, _) = rig.goal.rotation.to_euler(EulerRot::YXZ);
+            let yaw = yaw - mouse_motion.delta.x * rig.sensitivity;
+            let pitch = (pitch - mouse_motion.delta.y * rig.sensitivity).clamp(-1.5, 1.5);
+            rig.goal.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+        }
+        let mut direction = Vec3::ZERO;
+        for (key, towards) in [
+            (KeyCode::KeyW, *rig.goal.forward()),
+            (KeyCode::KeyS, *rig.goal.back()),
+            (KeyCode::KeyA, *rig.goal.left()),
+            (KeyCode::KeyD, *rig.goal.right()),
+            (KeyCode::KeyE, Vec3::Y),
+            (KeyCode::KeyQ, Vec3::NEG_Y),
+        ] {
+            if keys.pressed(key) {
+                direction += towards;
+            }
+        }
+        let boost = if keys.pressed(KeyCode::ShiftLeft) {
+            4.0
+        } else {
+            1.0
+        };
+        let step = direction.normalize_or_zero() * rig.fly_speed * boost * time.delta_secs();
+        rig.goal.translation += step;
+    }
+}
+
+fn orbit(
+    mut rigs: Query<&mut CameraRig>,
+    selection: Res<Selection>,
+    targets: Query<&GlobalTransform>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mouse_motion: Res<AccumulatedMouseMotion>,
+    mouse_scroll: Res<AccumulatedMouseScroll>,
+) {
+    for mut rig in rigs.iter_mut().filter(|rig| rig.mode == CameraMode::Orbit) {
+        if let Some(target) = selection.0.and_then(|entity| targets.get(entity).ok()) {
+            rig.focus = target.translation();
+        }
+        if mouse_buttons.pressed(MouseButton::Right) {
+            rig.yaw -= mouse_motion.delta.x * rig.sensitivity;
+            rig.pitch = (rig.pitch + mouse_motion.delta.y * rig.sensitivity).clamp(-1.5, 1.5);
+        }
+        // Zoom in proportion to the distance so it feels the same close up and far away.
+        let zoom = (1.0 - mouse_scroll.delta.y * 0.1).clamp(0.5, 1.5);
+        rig.distance = (rig.distance * zoom).clamp(rig.min_distance, rig.max_distance);
+        rig.goal = rig.orbit_goal();
+    }
+}
+
+/// Recent fighting, for the director to pick shots from.
+#[derive(Resource, Debug, Default)]
+struct Action {
+    /// When and where something happened, and how interesting it was.
+    moments: VecDeque<(f64, Vec3, f32)>,
+}
+
+fn remember_damage(
+    mut action: ResMut<Action>,
+    mut damage_events: EventReader<DamageEvent>,
+    targets: Query<&GlobalTransform>,
+    time: Res<Time<Real>>,
+) {
+    let now = time.elapsed_secs_f64();
+    for event in damage_events.read() {
+        if let Ok(target) = targets.get(event.target) {
+            action.moments.push_back((now, target.translation(), 1.0));
+        }
+    }
+    while action
+        .moments
+        .front()
+        .is_some_and(|(when, _, _)| now - when > ACTION_MEMORY)
+    {
+        action.moments.pop_front();
+    }
+}
+
+fn remember_kills(
+    mut action: ResMut<Action>,
+    mut destroyed: EventReader<ShipDestroyed>,
+    time: Res<Time<Real>>,
+) {
+    let now = time.elapsed_secs_f64();
+    for event in destroyed.read() {
+        action.moments.push_back((now, event.position, 5.0));
+    }
+}
+
+/// Cuts to wherever the most fighting happened lately, then slowly circles it.
+fn direct(mut rigs: Query<&mut CameraRig>, action: Res<Action>, time: Res<Time<Real>>) {
+    let now = time.elapsed_secs_f64();
+    for mut rig in rigs
+        .iter_mut()
+        .filter(|rig| rig.mode == CameraMode::Director)
+    {
+        if now >= rig.next_cut {
+            rig.next_cut = now + DIRECTOR_SHOT;
+            let hot_spot = action
+                .moments
+                .iter()
+                .map(|&(_, position, _)| {
+                    let heat: f32 = action
+                        .moments
+                        .iter()
+                        .filter(|(_, other, _)| other.distance(position) < HOT_SPOT_RADIUS)
+                        .map(|(_, _, weight)| weight)
+                        .sum();
+                    (position, heat)
+                })
+                .max_by(|(_, a), (_, b)| a.total_cmp(b));
+            if let Some((focus, _)) = hot_spot {
+                let mut rng = thread_rng();
+                rig.focus = focus;
+                rig.yaw = rng.gen_range(0.0..std::f32::consts::TAU);
+                rig.pitch = rng.gen_range(0.15..0.6);
+                rig.distance = rng
+                    .gen_range(35.0..70.0_f32)
+                    .clamp(rig.min_distance, rig.max_distance);
+            }
+        }
+        rig.yaw += 0.08 * time.delta_secs();
+        rig.goal = rig.orbit_goal();
+    }
+}
+
+/// Selects the ship closest to the cursor on screen, chasing fighters and orbiting capital ships.
+fn pick_ship(
+    mut selection: ResMut<Selection>,
+    mut rigs: Query<(&mut CameraRig, &Camera, &GlobalTransform)>,
+    ships: Query<(Entity, &GlobalTransform, Has<CapitalShip>), Or<(With<Ship>, With<CapitalShip>)>>,
+    windows: Query<&Window>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+) {
+    if !mouse_buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(cursor) = windows.iter().find_map(Window::cursor_position) else {
+        return;
+    };
+    for (mut rig, camera, camera_transform) in rigs.iter_mut() {
+        let picked = ships
+            .iter()
+            .filter_map(|(entity, transform, capital)| {
+                let on_screen = camera
+                    .world_to_viewport(camera_transform, transform.translation())
+                    .ok()?;
+                let distance = on_screen.distance(cursor);
+                // Capital ships are big enough to click anywhere near their middle.
+                let radius = if capital {
+                    PICK_RADIUS * 4.0
+                } else {
+                    PICK_RADIUS
+                };
+                (distance < radius).then_some((
+                    entity,
+                    distance,
+                    capital.then(|| transform.translation()),
+                ))
+            })
+            .min_by(|(_, a, _), (_, b, _)| a.total_cmp(b));
+        match picked {
+            Some((entity, _, Some(capital_ship))) => {
+                selection.0 = Some(entity);
+                rig.place(camera_transform.translation(), capital_ship);
+                rig.mode = CameraMode::Orbit;
+            }
+            Some((entity, _, None)) => {
+                selection.0 = Some(entity);
+                rig.mode = CameraMode::Chase;
+            }
+            None => {}
+        }
+    }
+}
+
+/// Tab chases the next ship, shift-Tab the previous one.
+fn cycle_ships(
+    mut selection: ResMut<Selection>,
+    mut rigs: Query<&mut CameraRig>,
+    ships: Query<Entity, With<Ship>>,
+    keys: Res<ButtonInput<KeyCode>>,
+) {
+    if !keys.just_pressed(KeyCode::Tab) {
+        return;
+    }
+    let mut ships: Vec<Entity> = ships.iter().collect();
+    if ships.is_empty() {
+        return;
+    }
+    ships.sort();
+    if keys.pressed(KeyCode::ShiftLeft) {
+        ships.reverse();
+    }
+    let next = selection
+        .0
+        .and_then(|current| ships.iter().position(|&ship| ship == current))
+        .map_or(0, |index| (index + 1) % ships.len());
+    selection.0 = Some(ships[next]);
+    for mut rig in rigs.iter_mut() {
+        rig.mode = CameraMode::Chase;
+    }
+}
+
+/// When the selected ship is destroyed, follow whoever killed it, or failing that the closest
+/// ship left on its team.
+fn hand_off_selection(
+    mut selection: ResMut<Selection>,
+    mut destroyed: EventReader<ShipDestroyed>,
+    ships: Query<(Entity, &GlobalTransform, &Team), With<Ship>>,
+    // The selected ship is gone by the time it's reported destroyed, so remember its team.
+    mut selected_team: Local<Option<Team>>,
+) {
+    for event in destroyed.read() {
+        if selection.0 != Some(event.entity) {
+            continue;
+        }
+        let killer = event.killer.filter(|&killer| ships.contains(killer));
+        selection.0 = killer.or_else(|| {
+            ships
+                .iter()
+                .filter(|(_, _, team)| Some(**team) == *selected_team)
+                .min_by(|(_, a, _), (_, b, _)| {
+                    let a = a.translation().distance_squared(event.position);
+                    let b = b.translation().distance_squared(event.position);
+                    a.total_cmp(&b)
+                })
+                .map(|(entity, _, _)| entity)
+        });
+    }
+    if let Some((_, _, team)) = selection.0.and_then(|entity| ships.get(entity).ok()) {
+        *selected_team = Some(*team);
+    }
+}
+
+/// Sits behind and above the selected ship, looking a little ahead of it.
+fn chase(
+    mut rigs: Query<&mut CameraRig>,
+    selection: Res<Selection>,
+    targets: Query<&GlobalTransform>,
+) {
+    let Some(target) = selection.0.and_then(|entity| targets.get(entity).ok()) else {
+        return;
+    };
+    for mut rig in rigs.iter_mut().filter(|rig| rig.mode == CameraMode::Chase) {
+        rig.goal = rig.chase_goal(target);
+    }
+}
+
+fn ease_camera(mut rigs: Query<(&CameraRig, &mut Transform)>, time: Res<Time<Real>>) {
+    for (rig, mut transform) in rigs.iter_mut() {
+        let smoothing = match rig.mode {
+            CameraMode::Chase => rig.chase_smoothing,
+            _ => rig.smoothing,
+        };
+        let t = 1.0 - (-smoothing * time.delta_secs()).exp();
+        transform.translation = transform.translation.lerp(rig.goal.translation, t);
+        transform.rotation = transform.rotation.slerp(rig.goal.rotation, t);
+    }
+}
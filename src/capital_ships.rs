@@ -1,137 +1,229 @@
-use std::time::Duration;
-
-use crate::{spawners::Spawner, ShipAssets, Team};
+use crate::{
+    health::{Health, Kills, Shield},
+    match_state::MatchEntity,
+    scenario::SpawnerLayout,
+    spawners::Spawner,
+    turrets::{turret_beam, turret_gun, turret_point_defense, turret_railgun, Turret},
+    ShipAssets, Team,
+};
+use avian3d::prelude::Collider;
 use bevy::{
-    animation::{AnimationTarget, AnimationTargetId}, prelude::*, render::mesh::CylinderMeshBuilder, utils::HashMap,
+    animation::{AnimationTarget, AnimationTargetId},
+    ecs::system::EntityCommand,
+    prelude::*,
+    render::mesh::CylinderMeshBuilder,
+    utils::HashMap,
 };
 use glam::vec3;
 
 pub fn plugin(app: &mut App) {
+    app.register_type::<CapitalShip>();
+    app.register_type::<Hull>();
     app.add_systems(PreStartup, setup);
 }
-fn setup(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+fn setup(mut commands: Commands, meshes: Option<ResMut<Assets<Mesh>>>) {
+    let mut assets = CapitalShipAssets {
+        meshes: HashMap::new(),
+        turret: None,
+    };
+    // Headless runs register `Assets<Mesh>` for avian, so the meshes are still built there. They're
+    // never attached though, as headless runs have no ship materials to pair them with.
+    let Some(mut meshes) = meshes else {
+        commands.insert_resource(assets);
+        return;
+    };
     let mesh = meshes.add(CylinderMeshBuilder {
         resolution: 6,
         segments: 1,
         caps: true,
         ..default()
     });
-    let mut meshes = HashMap::new();
-    meshes.insert(Team::Red, mesh.clone());
-    meshes.insert(Team::Blue, mesh);
-    commands.insert_resource(CapitalShipAssets { meshes });
+    for team in Team::ALL {
+        assets.meshes.insert(team, mesh.clone());
+    }
+    assets.turret = Some(meshes.add(Cuboid::new(1.5, 1.0, 3.0)));
+    commands.insert_resource(assets);
 }
+/// Turns an empty entity into a capital ship, e.g. `commands.spawn_empty().queue(..)`.
 pub struct SpawnCapitalShip {
     pub transform: Transform,
     pub team: Team,
+    pub spawners: SpawnerLayout,
+    /// Whether to mount turrets on the hull.
+    pub turrets: bool,
 }
 
+/// Root of a capital ship. Hits on any of its hull sections damage the whole ship.
+#[derive(Component, Reflect, Default)]
+#[require(
+    Transform,
+    Visibility,
+    MatchEntity,
+    Health(|| Health::new(400.0)),
+    Shield(|| Shield::new(100.0, 5.0)),
+    Kills
+)]
+pub struct CapitalShip;
+
+/// One of the colliders making up a capital ship's hull, which fighters steer around.
+#[derive(Component, Reflect, Default)]
+pub struct Hull;
+
 #[derive(Resource, Clone)]
 pub struct CapitalShipAssets {
     meshes: HashMap<Team, Handle<Mesh>>,
+    pub turret: Option<Handle<Mesh>>,
 }
 
 const LENGTH: f32 = 80.0;
+/// Sideways distance of the launch bays from the keel. The outer wings reach out to 15 units, and
+/// fighters need room for their colliders or they spawn inside the hull and get shoved out.
+const BAY_OFFSET: f32 = 17.0;
 
-impl Command for SpawnCapitalShip {
-    fn apply(self, world: &mut World) {
+impl EntityCommand for SpawnCapitalShip {
+    fn apply(self, root: Entity, world: &mut World) {
         let capital_ship_assets = world.resource::<CapitalShipAssets>().clone();
         let ship_assets = world.resource::<ShipAssets>().clone();
         let root_name = Name::new(format!("Capital Ship {:?}", self.team));
-        let (graph, animation_index, target) = {
+        world
+            .entity_mut(root)
+            .insert((CapitalShip, self.team, self.transform));
+        // The fly-in animation is skipped when running without the animation plugin.
+        if world.contains_resource::<Assets<AnimationGraph>>() {
             let mut animation = AnimationClip::default();
-            let target =
-                AnimationTargetId::from_name(&root_name);
+            let target = AnimationTargetId::from_name(&root_name);
 
             animation.add_curve_to_target(
                 target,
                 UnevenSampleAutoCurve::new([
-                    (0.3, self.transform.translation - (self.transform.forward() * -2000.0)),
-                    (1.2, self.transform.translation)
+                    (
+                        0.3,
+                        self.transform.translation - (self.transform.forward() * -2000.0),
+                    ),
+                    (1.2, self.transform.translation),
                 ])
-                    .map(TranslationCurve)
-                    .expect("animation curve samples should be valid"),
+                .map(TranslationCurve)
+                .expect("animation curve samples should be valid"),
             );
             let mut animations = world.resource_mut::<Assets<AnimationClip>>();
-            let (graph, index) = AnimationGraph::from_clip(animations.add(animation));
+            let (graph, animation_index) = AnimationGraph::from_clip(animations.add(animation));
             let mut graphs = world.resource_mut::<Assets<AnimationGraph>>();
             let graph = graphs.add(graph);
-            (graph, index, target)
-        };
-        let mut player = AnimationPlayer::default();
-        player.play(animation_index);
-
-        let root = world.spawn_empty().id();
-        world.entity_mut(root)
-            .insert((
-                self.transform,
-                Visibility::default(),
+            let mut player = AnimationPlayer::default();
+            player.play(animation_index);
+            world.entity_mut(root).insert((
                 AnimationGraphHandle(graph),
                 player,
                 AnimationTarget {
                     id: target,
                     player: root,
                 },
-            ))
-            .with_children(|child_builder| {
-                child_builder.spawn((
-                    Mesh3d(
-                        capital_ship_assets
-                            .meshes
-                            .get(&self.team)
-                            .expect("capital ship assets are missing")
-                            .clone(),
-                    ),
+            ));
+        }
+        let material = ship_assets.materials.get(&self.team).cloned();
+        let visuals = capital_ship_assets
+            .meshes
+            .get(&self.team)
+            .cloned()
+            .zip(material.clone())
+            .map(|(mesh, material)| (Mesh3d(mesh), MeshMaterial3d(material)));
+        let turret_visuals = capital_ship_assets
+            .turret
+            .clone()
+            .zip(material)
+            .map(|(mesh, material)| (Mesh3d(mesh), MeshMaterial3d(material)));
+        world.entity_mut(root).with_children(|child_builder| {
+            let mut hull = child_builder.spawn((
+                Hull,
+                Collider::cylinder(0.5, 1.0),
+                self.team.collision_layers(),
+                Transform {
+                    scale: Vec3::new(10., LENGTH, 10.),
+                    rotation: Quat::from_axis_angle(Vec3::X, 90.0_f32.to_radians()),
+                    ..default()
+                },
+            ));
+            if let Some(visuals) = visuals.clone() {
+                hull.insert(visuals);
+            }
+            // Wings
+            for side in [-2., -1., 1., 2.] {
+                let mut wing = child_builder.spawn((
+                    Hull,
+                    Collider::cylinder(0.5, 1.0),
+                    self.team.collision_layers(),
                     Transform {
-                        scale: Vec3::new(10., LENGTH, 10.),
+                        translation: Vec3::new(5. * side, 0., -LENGTH * 0.15 * side.abs()),
+                        scale: Vec3::new(10., LENGTH * 0.6, 10.),
                         rotation: Quat::from_axis_angle(Vec3::X, 90.0_f32.to_radians()),
-                        ..default()
                     },
-                    MeshMaterial3d(ship_assets.materials.get(&self.team).unwrap().clone()),
                 ));
-                // Wings
-                for side in [-2., -1., 1., 2.] {
-                    child_builder.spawn((
-                        Mesh3d(
-                            capital_ship_assets
-                                .meshes
-                                .get(&self.team)
-                                .expect("capital ship assets are missing")
-                                .clone(),
-                        ),
-                        Transform {
-                            translation: Vec3::new(5. * side, 0., -LENGTH * 0.15 * side.abs()),
-                            scale: Vec3::new(10., LENGTH * 0.6, 10.),
-                            rotation: Quat::from_axis_angle(Vec3::X, 90.0_f32.to_radians()),
-                        },
-                        MeshMaterial3d(ship_assets.materials.get(&self.team).unwrap().clone()),
+                if let Some(visuals) = visuals.clone() {
+                    wing.insert(visuals);
+                }
+            }
+            // Turrets along the top and bottom of the hull
+            let rows = [
+                (5.5, Quat::IDENTITY),
+                (-5.5, Quat::from_rotation_z(180.0_f32.to_radians())),
+            ];
+            for (y, base) in rows.into_iter().filter(|_| self.turrets) {
+                for z in [-30., -10., 10., 30.] {
+                    // The top row mixes in heavier weapons, the bottom row fires lasers and
+                    // guards the ends of the hull against missiles.
+                    let gun = match (y > 0.0, z.abs() > 20.0) {
+                        (true, true) => turret_railgun(),
+                        (true, false) => turret_beam(),
+                        (false, true) => turret_point_defense(),
+                        (false, false) => turret_gun(),
+                    };
+                    let mut mount = Turret::new(base);
+                    if gun.point_defense {
+                        mount.turn_rate = 360.0;
+                    }
+                    let mut turret = child_builder.spawn((
+                        mount,
+                        gun,
+                        self.team,
+                        Collider::sphere(1.5),
+                        self.team.collision_layers(),
+                        Transform::from_translation(vec3(0., y, z)).with_rotation(base),
                     ));
+                    if let Some(visuals) = turret_visuals.clone() {
+                        turret.insert(visuals);
+                    }
                 }
-                // Fighter Spawners
-                for y in [-0.75, 0.75] {
-                    for z in -2..=4 {
-                        let z = z as f32;
-                        for side in [-1., 1.] {
-                            child_builder.spawn((
-                                Spawner {
-                                    max: Some(200),
-                                    delay: Duration::from_secs_f32(0.2),
-                                    team: self.team,
-                                    last_spawn: None,
-                                    spawned: 0,
-                                },
-                                Transform::from_translation(vec3(
-                                    4. * side,
-                                    y * 5.,
-                                    (4.0 * z) - LENGTH * 0.30,
-                                ))
-                                .with_rotation(
-                                    Quat::from_rotation_y(-side * 90.0_f32.to_radians()),
-                                ),
-                            ));
-                        }
+            }
+            // Fighter Spawners
+            let layout = &self.spawners;
+            for deck in 0..layout.decks {
+                let y = if layout.decks > 1 {
+                    -0.75 + 1.5 * deck as f32 / (layout.decks - 1) as f32
+                } else {
+                    0.0
+                };
+                for bay in 0..layout.bays {
+                    let z = bay as f32 - 2.0;
+                    for side in [-1., 1.] {
+                        child_builder.spawn((
+                            Spawner {
+                                max: layout.max_per_bay,
+                                delay: layout.delay(),
+                                team: self.team,
+                                classes: layout.classes.clone(),
+                                squadron: layout.squadron.clone(),
+                                ..default()
+                            },
+                            Transform::from_translation(vec3(
+                                BAY_OFFSET * side,
+                                y * 5.,
+                                (4.0 * z) - LENGTH * 0.30,
+                            ))
+                            .with_rotation(Quat::from_rotation_y(-side * 90.0_f32.to_radians())),
+                        ));
                     }
                 }
-            });
+            }
+        });
     }
 }
@@ -0,0 +1,183 @@
+use avian3d::prelude::LinearVelocity;
+use bevy::{math::vec3, prelude::*};
+use bevy_spatial::{kdtree::KDTree3A, SpatialAccess};
+use serde::Deserialize;
+
+use crate::{
+    ships::{Steering, SteeringSystems},
+    targeting::CurrentTarget,
+    weapons::Missile,
+    Team, TrackedByKDTree,
+};
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<Flocking>();
+    app.register_type::<Squadron>();
+    app.register_type::<Formation>();
+    app.add_systems(
+        FixedUpdate,
+        (flock, fly_in_formation, disband_leaderless).in_set(SteeringSystems),
+    );
+}
+
+/// Boids style steering weights: keep apart from everyone nearby, and fly with the rest of the
+/// team.
+#[derive(Component, Reflect, Debug, Clone)]
+pub struct Flocking {
+    /// How far away neighbours are taken into account.
+    pub radius: f32,
+    /// Neighbours closer than this are pushed away from.
+    pub separation_distance: f32,
+    pub separation: f32,
+    /// Match the heading of nearby team mates.
+    pub alignment: f32,
+    /// Fly towards the centre of nearby team mates.
+    pub cohesion: f32,
+}
+
+impl Default for Flocking {
+    fn default() -> Self {
+        Self {
+            radius: 8.0,
+            separation_distance: 2.5,
+            separation: 3.0,
+            alignment: 0.5,
+            cohesion: 0.3,
+        }
+    }
+}
+
+/// A wingman flying in formation with its squadron's leader.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct Squadron {
+    pub leader: Entity,
+    /// Position in the formation, the leader is slot 0.
+    pub slot: usize,
+    pub formation: Formation,
+    /// Distance between neighbouring slots.
+    pub spacing: f32,
+}
+
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Formation {
+    /// Wingmen trail behind the leader on alternating sides.
+    #[default]
+    V,
+    /// Wingmen fly abreast of the leader on alternating sides.
+    Line,
+    /// Wingmen trail diagonally off the leader's right wing.
+    Echelon,
+}
+
+impl Formation {
+    /// Where the given slot sits relative to the leader, in the leader's local space.
+    pub fn offset(self, slot: usize, spacing: f32) -> Vec3 {
+        let rank = slot.div_ceil(2) as f32;
+        let side = if slot % 2 == 1 { -1.0 } else { 1.0 };
+        match self {
+            Formation::V => vec3(side * rank, 0.0, rank) * spacing,
+            Formation::Line => vec3(side * rank, 0.0, 0.0) * spacing,
+            Formation::Echelon => vec3(slot as f32, 0.0, slot as f32) * spacing,
+        }
+    }
+}
+
+/// Separation from every nearby ship, alignment and cohesion with nearby team mates.
+fn flock(
+    mut ships: Query<(Entity, &GlobalTransform, &Team, &Flocking, &mut Steering)>,
+    neighbours: Query<(&Team, &LinearVelocity)>,
+    missiles: Query<(), With<Missile>>,
+    tree: Res<KDTree3A<TrackedByKDTree>>,
+) {
+    ships
+        .par_iter_mut()
+        .for_each(|(entity, transform, team, flocking, mut steering)| {
+            let position = transform.translation();
+            let mut separation = Vec3::ZERO;
+            let mut heading = Vec3::ZERO;
+            let mut centre = Vec3::ZERO;
+            let mut mates = 0;
+            for (other_position, other) in
+                tree.within_distance(transform.translation_vec3a(), flocking.radius)
+            {
+                // Missiles are in the tree so point defense can find them, they're not ships.
+                let Some(other) =
+                    other.filter(|&other| other != entity && !missiles.contains(other))
+                else {
+                    continue;
+                };
+                let away = position - Vec3::from(other_position);
+                let distance = away.length();
+                if distance < flocking.separation_distance {
+                    // Push harder the closer the neighbour is.
+                    separation +=
+                        away.normalize_or_zero() * (1.0 - distance / flocking.separation_distance);
+                }
+                if let Ok((other_team, velocity)) = neighbours.get(other) {
+                    if other_team == team {
+                        heading += velocity.0.normalize_or_zero();
+                        centre += Vec3::from(other_position);
+                        mates += 1;
+                    }
+                }
+            }
+            steering.0 += separation * flocking.separation;
+            if mates > 0 {
+                steering.0 += heading.normalize_or_zero() * flocking.alignment;
+                let centre = centre / mates as f32;
+                steering.0 += (centre - position).normalize_or_zero() * flocking.cohesion;
+            }
+        });
+}
+
+/// Wingmen without a target of their own hold their slot next to the leader.
+fn fly_in_formation(
+    mut wingmen: Query<(&GlobalTransform, &Squadron, &mut Steering), Without<CurrentTarget>>,
+    leaders: Query<&GlobalTransform>,
+) {
+    wingmen
+        .par_iter_mut()
+        .for_each(|(transform, squadron, mut steering)| {
+            let Ok(leader) = leaders.get(squadron.leader) else {
+                return;
+            };
+            let slot =
+                leader.transform_point(squadron.formation.offset(squadron.slot, squadron.spacing));
+            let to_slot = slot - transform.translation();
+            // Close the gap, then settle onto the leader's heading once in position.
+            steering.0 +=
+                (to_slot / squadron.spacing).clamp_length_max(2.0) + leader.forward() * 1.5;
+        });
+}
+
+fn disband_leaderless(
+    mut commands: Commands,
+    wingmen: Query<(Entity, &Squadron)>,
+    leaders: Query<(), With<Steering>>,
+) {
+    for (entity, squadron) in wingmen.iter() {
+        if !leaders.contains(squadron.leader) {
+            commands.entity(entity).remove::<Squadron>();
+        }
+    }
+}
+
+/// How a [`Spawner`](crate::spawners::Spawner) groups the ships it launches.
+#[derive(Reflect, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SquadronLayout {
+    /// Ships per squadron, including the leader.
+    pub size: usize,
+    pub formation: Formation,
+    pub spacing: f32,
+}
+
+impl Default for SquadronLayout {
+    fn default() -> Self {
+        Self {
+            size: 4,
+            formation: Formation::V,
+            spacing: 3.0,
+        }
+    }
+}
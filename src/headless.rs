@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+
+use crate::match_state::MatchState;
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Last, end_headless_run);
+}
+
+/// Bookkeeping for a battle running without a window.
+///
+/// The run ends once the match is decided, or after `max_ticks` have elapsed.
+#[derive(Resource, Debug)]
+pub struct HeadlessRun {
+    pub max_ticks: Option<u64>,
+    pub ticks: u64,
+}
+
+impl HeadlessRun {
+    pub fn new(max_ticks: Option<u64>) -> Self {
+        Self {
+            max_ticks,
+            ticks: 0,
+        }
+    }
+}
+
+fn end_headless_run(
+    mut run: ResMut<HeadlessRun>,
+    state: Res<State<MatchState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    run.ticks += 1;
+    match state.get() {
+        MatchState::Victory(winner) => {
+            info!("{winner:?} won after {} ticks", run.ticks);
+            exit.send(AppExit::Success);
+        }
+        MatchState::Draw => {
+            info!("Every team was destroyed after {} ticks", run.ticks);
+            exit.send(AppExit::Success);
+        }
+        _ if run.max_ticks.is_some_and(|max| run.ticks >= max) => {
+            info!("Stopping after {} ticks without a winner", run.ticks);
+            exit.send(AppExit::Success);
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,175 @@
+use bevy::prelude::*;
+
+use crate::Team;
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<Health>();
+    app.register_type::<Shield>();
+    app.register_type::<Kills>();
+    app.add_event::<DamageEvent>();
+    app.add_event::<ShipDestroyed>();
+    app.add_systems(
+        FixedUpdate,
+        (regenerate_shields, apply_damage)
+            .chain()
+            .in_set(DamageSystems),
+    );
+    app.add_systems(FixedUpdate, count_kills.after(DamageSystems));
+    app.add_systems(FixedLast, despawn_destroyed);
+}
+
+/// Systems that turn [`DamageEvent`]s into [`ShipDestroyed`] events.
+///
+/// Anything reacting to damage in the same frame should run after this set.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DamageSystems;
+
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+/// Absorbs damage before [`Health`] does, and recharges after a while without being hit.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
+#[require(Health)]
+pub struct Shield {
+    pub current: f32,
+    pub max: f32,
+    /// Shield points restored per second.
+    pub regen_rate: f32,
+    /// Seconds without taking damage before the shield starts recharging.
+    pub regen_delay: f32,
+    pub last_damaged: f64,
+}
+
+impl Shield {
+    pub fn new(max: f32, regen_rate: f32) -> Self {
+        Self {
+            current: max,
+            max,
+            regen_rate,
+            regen_delay: 3.0,
+            last_damaged: f64::NEG_INFINITY,
+        }
+    }
+}
+
+/// Request to damage an entity with [`Health`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    /// The entity responsible for the damage, usually whoever fired the shot.
+    pub source: Option<Entity>,
+    /// Where the damage landed, if it came from a hit.
+    pub point: Option<Vec3>,
+    /// Part of damage dealt every tick, e.g. by a beam, rather than a hit of its own.
+    pub continuous: bool,
+    /// Caught in a blast along with something else, only the first target of a blast is a hit.
+    pub splash: bool,
+}
+
+/// Sent once when an entity's [`Health`] runs out. The entity is despawned in [`FixedLast`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ShipDestroyed {
+    pub entity: Entity,
+    pub killer: Option<Entity>,
+    pub position: Vec3,
+}
+
+fn regenerate_shields(mut shields: Query<&mut Shield>, time: Res<Time>) {
+    let now = time.elapsed_secs_f64();
+    for mut shield in shields.iter_mut() {
+        if shield.current >= shield.max || now < shield.last_damaged + f64::from(shield.regen_delay)
+        {
+            continue;
+        }
+        shield.current = (shield.current + shield.regen_rate * time.delta_secs()).min(shield.max);
+    }
+}
+
+fn apply_damage(
+    mut damage_events: EventReader<DamageEvent>,
+    mut destroyed: EventWriter<ShipDestroyed>,
+    mut targets: Query<(&mut Health, Option<&mut Shield>, &GlobalTransform)>,
+    time: Res<Time>,
+) {
+    for event in damage_events.read() {
+        let Ok((mut health, shield, transform)) = targets.get_mut(event.target) else {
+            continue;
+        };
+        if health.is_dead() {
+            continue;
+        }
+        let mut amount = event.amount;
+        if let Some(mut shield) = shield {
+            shield.last_damaged = time.elapsed_secs_f64();
+            let absorbed = amount.min(shield.current);
+            shield.current -= absorbed;
+            amount -= absorbed;
+        }
+        health.current -= amount;
+        if health.is_dead() {
+            destroyed.send(ShipDestroyed {
+                entity: event.target,
+                killer: event.source,
+                position: transform.translation(),
+            });
+        }
+    }
+}
+
+/// Ships destroyed by this entity, or by turrets mounted on it.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+#[reflect(Component)]
+pub struct Kills(pub u32);
+
+fn count_kills(
+    mut destroyed: EventReader<ShipDestroyed>,
+    mut kills: Query<&mut Kills>,
+    victims: Query<(), With<Team>>,
+    parents: Query<&Parent>,
+) {
+    for event in destroyed.read() {
+        let Some(killer) = event.killer else {
+            continue;
+        };
+        // Asteroids don't count.
+        if !victims.contains(event.entity) {
+            continue;
+        }
+        let credited = std::iter::once(killer)
+            .chain(parents.iter_ancestors(killer))
+            .find(|&entity| kills.contains(entity));
+        if let Some(mut kills) = credited.and_then(|entity| kills.get_mut(entity).ok()) {
+            kills.0 += 1;
+        }
+    }
+}
+
+fn despawn_destroyed(mut commands: Commands, mut destroyed: EventReader<ShipDestroyed>) {
+    for event in destroyed.read() {
+        if let Some(entity) = commands.get_entity(event.entity) {
+            entity.try_despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,253 @@
+//! Panel listing the selected ship's components. Everything is read through reflection, so a
+//! component shows up as soon as it's added to [`inspected`] and registered with
+//! `#[reflect(Component)]`.
+//!
+//! `I` toggles the panel. `[` and `]` pick a number or flag, `-` and `=` change it.
+use std::any::TypeId;
+
+use bevy::{
+    prelude::*,
+    reflect::{GetPath, PartialReflect, ReflectRef},
+};
+
+use crate::{
+    camera::Selection,
+    health::{Health, Kills, Shield},
+    lasers::Gun,
+    ship_classes::ShipClass,
+    targeting::CurrentTarget,
+    Team,
+};
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<Inspector>();
+    app.add_systems(Startup, setup_inspector);
+    app.add_systems(
+        Update,
+        (toggle_inspector, edit_field, update_inspector).chain(),
+    );
+}
+
+/// Components shown for the selected entity and the first of its guns, in order.
+fn inspected() -> [TypeId; 7] {
+    [
+        TypeId::of::<Team>(),
+        TypeId::of::<ShipClass>(),
+        TypeId::of::<Health>(),
+        TypeId::of::<Shield>(),
+        TypeId::of::<CurrentTarget>(),
+        TypeId::of::<Kills>(),
+        TypeId::of::<Gun>(),
+    ]
+}
+
+#[derive(Resource, Debug, Default)]
+struct Inspector {
+    hidden: bool,
+    /// Index into `fields` of the field being edited.
+    cursor: usize,
+    /// Fields that can be edited, as shown last frame.
+    fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone)]
+struct Field {
+    entity: Entity,
+    component: TypeId,
+    /// Reflection path to the field within its component.
+    path: String,
+}
+
+#[derive(Component)]
+struct InspectorText;
+
+fn setup_inspector(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(24.0),
+            top: Val::Px(48.0),
+            ..default()
+        })
+        .with_child((
+            Text::default(),
+            TextFont {
+                font: asset_server.load("IBM Plex Mono/IBMPlexMono-Regular.ttf"),
+                font_size: 14.,
+                ..default()
+            },
+            InspectorText,
+        ));
+}
+
+fn toggle_inspector(mut inspector: ResMut<Inspector>, keys: Res<ButtonInput<KeyCode>>) {
+    if keys.just_pressed(KeyCode::KeyI) {
+        inspector.hidden = !inspector.hidden;
+    }
+    if inspector.fields.is_empty() {
+        return;
+    }
+    let len = inspector.fields.len();
+    if keys.just_pressed(KeyCode::BracketRight) {
+        inspector.cursor = (inspector.cursor + 1) % len;
+    }
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        inspector.cursor = (inspector.cursor + len - 1) % len;
+    }
+}
+
+/// Nudges the field under the cursor: numbers by 10% (or one for counts), flags are flipped.
+fn edit_field(world: &mut World) {
+    let keys = world.resource::<ButtonInput<KeyCode>>();
+    let direction = if keys.just_pressed(KeyCode::Equal) {
+        1.0
+    } else if keys.just_pressed(KeyCode::Minus) {
+        -1.0
+    } else {
+        return;
+    };
+    let inspector = world.resource::<Inspector>();
+    if inspector.hidden {
+        return;
+    }
+    let Some(field) = inspector.fields.get(inspector.cursor).cloned() else {
+        return;
+    };
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+    let Some(reflect_component) = registry
+        .get(field.component)
+        .and_then(|registration| registration.data::<ReflectComponent>())
+    else {
+        return;
+    };
+    let Ok(mut entity) = world.get_entity_mut(field.entity) else {
+        return;
+    };
+    let Some(mut component) = reflect_component.reflect_mut(&mut entity) else {
+        return;
+    };
+    let Ok(value) = component.reflect_path_mut(field.path.as_str()) else {
+        return;
+    };
+    if let Some(value) = value.try_downcast_mut::<f32>() {
+        *value = if *value == 0.0 {
+            direction * 0.1
+        } else {
+            *value * (1.0 + direction * 0.1)
+        };
+    } else if let Some(value) = value.try_downcast_mut::<u32>() {
+        *value = value.saturating_add_signed(direction as i32);
+    } else if let Some(value) = value.try_downcast_mut::<bool>() {
+        *value = !*value;
+    }
+}
+
+fn update_inspector(world: &mut World) {
+    let selected = world
+        .resource::<Selection>()
+        .0
+        .filter(|&entity| world.get_entity(entity).is_ok());
+    let mut text = String::new();
+    let mut fields = Vec::new();
+    let hidden = world.resource::<Inspector>().hidden;
+    if let (Some(selected), false) = (selected, hidden) {
+        let cursor = world.resource::<Inspector>().cursor;
+        let registry = world.resource::<AppTypeRegistry>().clone();
+        let registry = registry.read();
+        // Fighters carry their guns on hardpoints and capital ships on turrets, show the first.
+        let gun = world.get::<Children>(selected).and_then(|children| {
+            children
+                .iter()
+                .copied()
+                .find(|&child| world.get::<Gun>(child).is_some())
+        });
+        text.push_str(&format!("Entity {selected}\n"));
+        for entity in std::iter::once(selected).chain(gun) {
+            let entity_ref = world.entity(entity);
+            for type_id in inspected() {
+                let Some(registration) = registry.get(type_id) else {
+                    continue;
+                };
+                let Some(component) = registration
+                    .data::<ReflectComponent>()
+                    .and_then(|reflect_component| reflect_component.reflect(entity_ref))
+                else {
+                    continue;
+                };
+                let name = registration.type_info().type_path_table().short_path();
+                // Structs get a heading with a line per field, anything else fits on one line.
+                let rows: Vec<(String, String, &dyn PartialReflect)> = match component.reflect_ref()
+                {
+                    ReflectRef::Struct(component) => {
+                        text.push_str(&format!("{name}\n"));
+                        (0..component.field_len())
+                            .filter_map(|index| {
+                                let field_name = component.name_at(index)?;
+                                let value = component.field_at(index)?;
+                                Some((format!("  {field_name}"), format!(".{field_name}"), value))
+                            })
+                            .collect()
+                    }
+                    ReflectRef::TupleStruct(component) if component.field_len() == 1 => component
+                        .field(0)
+                        .map(|value| (name.to_string(), ".0".to_string(), value))
+                        .into_iter()
+                        .collect(),
+                    _ => vec![(
+                        name.to_string(),
+                        String::new(),
+                        component.as_partial_reflect(),
+                    )],
+                };
+                for (label, path, value) in rows {
+                    let editable = value.try_downcast_ref::<f32>().is_some()
+                        || value.try_downcast_ref::<u32>().is_some()
+                        || value.try_downcast_ref::<bool>().is_some();
+                    let marker = if editable && fields.len() == cursor {
+                        ">"
+                    } else {
+                        " "
+                    };
+                    text.push_str(&format!("{marker} {label}: {}\n", describe(world, value)));
+                    if editable {
+                        fields.push(Field {
+                            entity,
+                            component: type_id,
+                            path,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    let mut inspector = world.resource_mut::<Inspector>();
+    if inspector.cursor >= fields.len() {
+        inspector.cursor = 0;
+    }
+    inspector.fields = fields;
+    let mut query = world.query_filtered::<&mut Text, With<InspectorText>>();
+    if let Ok(mut inspector_text) = query.get_single_mut(world) {
+        inspector_text.0 = text;
+    }
+}
+
+/// Formats a reflected value for the panel, naming the ship behind an entity where it can.
+fn describe(world: &World, value: &dyn PartialReflect) -> String {
+    if let Some(value) = value.try_downcast_ref::<f32>() {
+        return format!("{value:.2}");
+    }
+    if let Some(value) = value.try_downcast_ref::<f64>() {
+        return format!("{value:.2}");
+    }
+    if let Some(&entity) = value.try_downcast_ref::<Entity>() {
+        return match (world.get::<Team>(entity), world.get::<ShipClass>(entity)) {
+            (Some(team), Some(class)) => format!("{entity} ({team:?} {class:?})"),
+            _ => entity.to_string(),
+        };
+    }
+    match value.reflect_ref() {
+        ReflectRef::Enum(value) => value.variant_name().to_string(),
+        _ => format!("{value:?}"),
+    }
+}
@@ -1,118 +1,377 @@
-use std::time::Duration;
-
-use avian3d::prelude::{SpatialQuery, SpatialQueryFilter};
 use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
     ecs::{component::ComponentId, world::DeferredWorld},
     prelude::*,
+    utils::HashMap,
+};
+use rand::Rng;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    health::DamageSystems,
+    match_state::MatchEntity,
+    simulation::SimRng,
+    weapons::{
+        detonate_flak, fire_beams, shoot, steer_missiles, Beam, Flak, Hits, Missile, Tracer,
+        Weapon, WeaponFired,
+    },
+    Team,
 };
-use rand::{thread_rng, Rng};
 
-use crate::lifetimes::DespawnAfter;
+/// Colours, sizes and glow of every kind of projectile.
+const PROJECTILE_STYLES: &str = "default.projectiles.ron";
+/// Damage dealt by a single laser bolt from a standard gun.
+const LASER_DAMAGE: f32 = 1.0;
+/// How fast lasers fly, in units per second.
+pub const LASER_SPEED: f32 = 35.0;
 
 pub fn plugin(app: &mut App) {
     app.register_type::<Laser>();
+    app.register_type::<Gun>();
+    app.register_type::<FriendlyFire>();
+    app.register_type::<ProjectileKind>();
+    app.register_type::<Weapon>();
+    app.register_type::<Missile>();
+    app.register_type::<Flak>();
+    app.register_type::<Beam>();
+    app.register_type::<Tracer>();
+    app.add_event::<WeaponFired>();
+    app.init_resource::<FriendlyFire>();
+    app.init_asset::<ProjectileStyles>();
+    app.init_asset_loader::<ProjectileStylesLoader>();
     app.add_systems(Startup, setup);
-    app.add_systems(Update, (shoot, move_lasers));
-    app.add_systems(Update, laser_hit_detect);
+    app.add_systems(
+        Update,
+        build_laser_assets.run_if(on_event::<AssetEvent<ProjectileStyles>>),
+    );
+    app.add_systems(
+        FixedUpdate,
+        (
+            shoot,
+            steer_missiles,
+            move_lasers,
+            laser_hit_detect,
+            detonate_flak,
+            fire_beams,
+        )
+            .chain()
+            .before(DamageSystems),
+    );
 }
 
-#[derive(Resource, Reflect)]
-struct LaserAssets {
-    mesh: Handle<Mesh>,
-    material: Handle<StandardMaterial>,
+/// Meshes and materials for projectiles, built from [`ProjectileStyles`].
+#[derive(Resource, Reflect, Default)]
+pub struct LaserAssets {
+    pub meshes: HashMap<ProjectileKind, Handle<Mesh>>,
+    /// One unit long, for shots stretched to whatever they hit.
+    pub streaks: HashMap<ProjectileKind, Handle<Mesh>>,
+    /// Projectiles without a team have their own material too.
+    pub materials: HashMap<(ProjectileKind, Option<Team>), Handle<StandardMaterial>>,
 }
 
-#[derive(Component, Reflect)]
-pub struct Laser(Entity);
+impl LaserAssets {
+    pub fn visuals(
+        &self,
+        kind: ProjectileKind,
+        team: Option<Team>,
+    ) -> Option<(Mesh3d, MeshMaterial3d<StandardMaterial>)> {
+        Some((
+            Mesh3d(self.meshes.get(&kind)?.clone()),
+            MeshMaterial3d(self.materials.get(&(kind, team))?.clone()),
+        ))
+    }
+
+    /// Like [`LaserAssets::visuals`], for shots scaled along their length.
+    pub fn streak_visuals(
+        &self,
+        kind: ProjectileKind,
+        team: Option<Team>,
+    ) -> Option<(Mesh3d, MeshMaterial3d<StandardMaterial>)> {
+        Some((
+            Mesh3d(self.streaks.get(&kind)?.clone()),
+            MeshMaterial3d(self.materials.get(&(kind, team))?.clone()),
+        ))
+    }
+}
+
+/// What a [`Gun`] fires, which decides how its projectiles look.
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum ProjectileKind {
+    #[default]
+    Laser,
+    HeavyLaser,
+    TurretLaser,
+    Missile,
+    Flak,
+    Railgun,
+    Beam,
+    PointDefense,
+}
+
+impl ProjectileKind {
+    pub const ALL: [Self; 8] = [
+        Self::Laser,
+        Self::HeavyLaser,
+        Self::TurretLaser,
+        Self::Missile,
+        Self::Flak,
+        Self::Railgun,
+        Self::Beam,
+        Self::PointDefense,
+    ];
+}
+
+/// How projectiles look, loaded from a `.projectiles.ron` file.
+#[derive(Asset, TypePath, Deserialize, Debug, Clone)]
+pub struct ProjectileStyles {
+    /// Colour of each team's projectiles, those without a team are white.
+    #[serde(default)]
+    pub teams: HashMap<Team, [f32; 3]>,
+    #[serde(default)]
+    pub kinds: HashMap<ProjectileKind, ProjectileStyle>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProjectileStyle {
+    /// Ignored for railguns and beams.
+    pub length: f32,
+    /// Radius of the bolt.
+    pub thickness: f32,
+    /// How brightly the bolt glows, bright enough values bloom.
+    pub emissive: f32,
+    /// Used instead of the team colour, e.g. for weapons that look the same for everyone.
+    pub color: Option<[f32; 3]>,
+}
+
+impl Default for ProjectileStyle {
+    fn default() -> Self {
+        Self {
+            length: 0.8,
+            thickness: 0.01,
+            emissive: 100.0,
+            color: None,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct ProjectileStylesHandle(Handle<ProjectileStyles>);
 
 #[derive(Component, Reflect)]
+#[require(MatchEntity)]
+pub struct Laser {
+    /// The entity that fired the laser, it can't be hit by its own shots.
+    pub owner: Entity,
+    pub team: Option<Team>,
+    pub damage: f32,
+    /// Units per second.
+    pub speed: f32,
+    pub kind: ProjectileKind,
+}
+
+/// Whether lasers can hurt ships on the team that fired them, or teams allied with it.
+#[derive(Resource, Reflect, Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[reflect(Resource)]
+pub enum FriendlyFire {
+    /// Lasers pass through allies.
+    #[default]
+    Off,
+    On,
+    /// Allies are hit, but only take this fraction of the damage.
+    Reduced(f32),
+}
+
+/// Fires lasers along its forward axis whenever a hostile is inside its firing cone.
+#[derive(Component, Reflect, Debug, Clone)]
+#[reflect(Component)]
 #[component(on_insert=gun_on_add)]
 pub struct Gun {
+    /// How far lasers travel before fizzling out.
+    pub range: f32,
+    /// Damage dealt by each laser.
+    pub damage: f32,
+    /// Half angle of the firing cone in degrees.
+    pub arc: f32,
+    /// Seconds between bursts.
+    pub cooldown: f32,
+    /// Shots fired per burst.
+    pub burst: u32,
+    /// Seconds between shots within a burst.
+    pub burst_interval: f32,
+    pub weapon: Weapon,
+    pub projectile: ProjectileKind,
+    /// Shoots at hostile missiles, and turrets go after them before any ship.
+    pub point_defense: bool,
     pub(crate) last_fired: f64,
+    pub(crate) burst_remaining: u32,
 }
 
 fn gun_on_add(mut world: DeferredWorld, entity: Entity, _id: ComponentId) {
-    let last_fired = world.resource::<Time>().elapsed_secs_f64();
-    if let Some(mut entity) = world.get_mut::<Gun>(entity) {
-        entity.last_fired = last_fired;
+    let now = world.resource::<Time>().elapsed_secs_f64();
+    let stagger = world.resource_mut::<SimRng>().gen::<f64>();
+    if let Some(mut gun) = world.get_mut::<Gun>(entity) {
+        // Stagger guns so ships launched together don't fire in unison.
+        gun.last_fired = now - stagger * f64::from(gun.cooldown);
     }
 }
 
 impl Default for Gun {
     fn default() -> Self {
         Self {
-            last_fired: (thread_rng().gen::<f64>() - 1.0) * 5.0,
+            range: 40.0,
+            damage: LASER_DAMAGE,
+            arc: 10.0,
+            cooldown: 2.0,
+            burst: 2,
+            burst_interval: 0.15,
+            weapon: Weapon::Laser,
+            projectile: ProjectileKind::Laser,
+            point_defense: false,
+            last_fired: 0.0,
+            burst_remaining: 0,
         }
     }
 }
 
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let mut mesh = Capsule3d::new(0.01, 0.8).mesh().build();
-    mesh.transform_by(Transform::from_rotation(Quat::from_rotation_x(
-        90.0_f32.to_radians(),
-    )));
-    let mesh = meshes.add(mesh);
-    let material = materials.add(StandardMaterial {
-        base_color: Color::linear_rgb(1.0, 0., 0.),
-        emissive: LinearRgba::rgb(100.0, 1.0, 1.0),
-        ..default()
-    });
-    commands.insert_resource(LaserAssets { mesh, material });
-}
-
-fn move_lasers(mut lasers: Query<&mut Transform, With<Laser>>, time: Res<Time>) {
-    for mut transform in lasers.iter_mut() {
-        let forward = transform.forward();
-        transform.translation += forward * 35.0 * time.delta_secs();
+impl Gun {
+    pub(crate) fn ready(&self, now: f64) -> bool {
+        if self.burst_remaining > 0 {
+            now >= self.last_fired + f64::from(self.burst_interval)
+        } else {
+            now >= self.last_fired + f64::from(self.cooldown)
+        }
+    }
+
+    pub(crate) fn in_arc(&self, transform: &GlobalTransform, position: Vec3) -> bool {
+        let offset = position - transform.translation();
+        offset.length_squared() <= self.range * self.range
+            && transform.forward().angle_between(offset) <= self.arc.to_radians()
+    }
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(ProjectileStylesHandle(asset_server.load(PROJECTILE_STYLES)));
+}
+
+#[derive(Default)]
+struct ProjectileStylesLoader;
+
+#[derive(Debug, Error)]
+enum ProjectileStylesLoaderError {
+    #[error("could not read projectile styles: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse projectile styles: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for ProjectileStylesLoader {
+    type Asset = ProjectileStyles;
+    type Settings = ();
+    type Error = ProjectileStylesLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<ProjectileStyles, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["projectiles.ron"]
     }
 }
 
-fn shoot(
+/// Builds [`LaserAssets`] once the styles load, and again whenever they're edited.
+fn build_laser_assets(
     mut commands: Commands,
-    mut guns: Query<(Entity, &GlobalTransform, &mut Gun)>,
-    laser_assets: Res<LaserAssets>,
-    time: Res<Time>,
+    handle: Res<ProjectileStylesHandle>,
+    styles: Res<Assets<ProjectileStyles>>,
+    meshes: Option<ResMut<Assets<Mesh>>>,
+    materials: Option<ResMut<Assets<StandardMaterial>>>,
 ) {
-    for (owner, transform, mut gun) in guns.iter_mut() {
-        let now = time.elapsed_secs_f64();
-        if gun.last_fired + 5.0 < now {
-            gun.last_fired = now;
-            commands.spawn((
-                Laser(owner),
-                Mesh3d(laser_assets.mesh.clone()),
-                MeshMaterial3d(laser_assets.material.clone()),
-                DespawnAfter::new(Duration::from_secs(2), &time),
-                transform.compute_transform(),
-                *transform,
-                Visibility::default(),
-            ));
+    // Headless runs have no renderer, lasers are spawned without meshes.
+    let (Some(mut meshes), Some(mut materials), Some(styles)) =
+        (meshes, materials, styles.get(&handle.0))
+    else {
+        return;
+    };
+    let mut assets = LaserAssets::default();
+    for kind in ProjectileKind::ALL {
+        let style = styles.kinds.get(&kind).cloned().unwrap_or_default();
+        let mut capsule = |length| {
+            let mut mesh = Capsule3d::new(style.thickness, length).mesh().build();
+            mesh.transform_by(Transform::from_rotation(Quat::from_rotation_x(
+                90.0_f32.to_radians(),
+            )));
+            meshes.add(mesh)
+        };
+        assets.meshes.insert(kind, capsule(style.length));
+        assets.streaks.insert(kind, capsule(1.0));
+        for team in Team::ALL.map(Some).into_iter().chain([None]) {
+            let [r, g, b] = style
+                .color
+                .or_else(|| team.and_then(|team| styles.teams.get(&team).copied()))
+                .unwrap_or([1.0; 3]);
+            let material = materials.add(StandardMaterial {
+                base_color: Color::linear_rgb(r, g, b),
+                emissive: LinearRgba::rgb(r, g, b) * style.emissive,
+                ..default()
+            });
+            assets.materials.insert((kind, team), material);
         }
     }
+    commands.insert_resource(assets);
+}
+
+fn move_lasers(mut lasers: Query<(&mut Transform, &Laser)>, time: Res<Time>) {
+    for (mut transform, laser) in lasers.iter_mut() {
+        let forward = transform.forward();
+        transform.translation += forward * laser.speed * time.delta_secs();
+    }
 }
 
 fn laser_hit_detect(
     mut commands: Commands,
-    lasers: Query<(Entity, &mut GlobalTransform, &Laser)>,
-    spatial_query: SpatialQuery,
+    lasers: Query<(Entity, &GlobalTransform, &Laser, Option<&Flak>)>,
+    mut hits: Hits,
+    time: Res<Time>,
 ) {
-    lasers.iter().for_each(|(entity, transform, Laser(owner))| {
-        if let Some(first_hit) = spatial_query.cast_ray(
-            transform.translation(),
-            Dir3::NEG_Z,
-            0.5,
-            true,
-            &SpatialQueryFilter::from_excluded_entities([entity, *owner]),
-        ) {
-            if let Some(e) = commands.get_entity(first_hit.entity) {
-                e.try_despawn_recursive();
-                if let Some(e) = commands.get_entity(entity) {
-                    e.try_despawn_recursive();
-                }
-            }
+    for (entity, transform, laser, flak) in lasers.iter() {
+        // Cover the distance travelled this frame so fast lasers don't skip past small ships.
+        let max_distance = (laser.speed * time.delta_secs()).max(0.5);
+        let origin = transform.translation();
+        let direction = transform.forward();
+        let Some((collider, distance)) = hits.cast(
+            origin,
+            direction,
+            max_distance,
+            laser.team,
+            [entity, laser.owner],
+        ) else {
+            continue;
+        };
+        let point = origin + direction * distance;
+        if let Some(flak) = flak {
+            // Flak bursts on contact rather than hurting only what it hit.
+            hits.burst(point, flak.radius, laser.damage, laser.owner, laser.team);
+        } else {
+            hits.damage(
+                collider,
+                laser.damage,
+                laser.owner,
+                laser.team,
+                point,
+                *direction,
+            );
+        }
+        if let Some(e) = commands.get_entity(entity) {
+            e.try_despawn_recursive();
         }
-    });
+    }
 }
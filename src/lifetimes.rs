@@ -4,7 +4,7 @@ use bevy::prelude::*;
 
 pub fn plugin(app: &mut App) {
     app.register_type::<DespawnAfter>();
-    app.add_systems(Update, despawn_after);
+    app.add_systems(FixedUpdate, despawn_after);
 }
 
 #[derive(Component, Reflect)]
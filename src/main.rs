@@ -1,39 +1,187 @@
 //! A minimal example that outputs "hello world"
+mod alliances;
+mod asteroids;
+mod camera;
 mod capital_ships;
+mod flocking;
 mod fps_overlay;
+mod headless;
+mod health;
+mod inspector;
 mod lasers;
 mod lifetimes;
+mod match_state;
+mod replay;
+mod scenario;
+mod ship_classes;
 mod ships;
+mod simulation;
 mod spawners;
+mod stats;
+mod targeting;
+mod trails;
+mod turrets;
+mod vfx;
+mod weapons;
 
 use std::time::Duration;
 
-use avian3d::PhysicsPlugins;
+use avian3d::{prelude::Gravity, PhysicsPlugins};
 use bevy::{
     core_pipeline::bloom::Bloom,
-    math::vec3,
+    ecs::schedule::ScheduleLabel,
+    log::LogPlugin,
     prelude::*,
     render::{
         settings::{PowerPreference, WgpuSettings},
         RenderPlugin,
     },
+    scene::ScenePlugin,
+    state::app::StatesPlugin,
+    time::TimeUpdateStrategy,
 };
+#[cfg(not(feature = "hot_reload"))]
 use bevy_embedded_assets::{EmbeddedAssetPlugin, PluginMode};
 use bevy_spatial::AutomaticUpdate;
-use capital_ships::SpawnCapitalShip;
+use camera::CameraRig;
 use fps_overlay::{FpsOverlayConfig, FpsOverlayPlugin};
+use headless::HeadlessRun;
+use replay::{RecordReplay, Replay, ReplayPlayback};
+use scenario::ScenarioPath;
 use ships::*;
+use simulation::{every, SimSeed, SIM_TICK};
 
 #[derive(Component, Default)]
 struct TrackedByKDTree;
 
+/// Rebuilds the KD-tree of [`TrackedByKDTree`] entities. Run from the fixed schedule every few
+/// ticks rather than on bevy_spatial's own timer, which counts from whenever the app started.
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct UpdateKDTree;
+
+fn update_kd_tree(world: &mut World) {
+    world.run_schedule(UpdateKDTree);
+}
+
+/// Command line arguments.
+///
+/// `--headless` runs the battle without a window or GPU.
+/// `--ticks <n>` limits how many ticks a headless run lasts.
+/// `--scenario <path>` picks the scenario to run, relative to the assets folder. Assets are
+/// embedded in the binary, so only scenarios present at build time can be picked unless built
+/// with the `hot_reload` feature.
+/// `--seed <n>` replays a battle, the same seed and scenario always play out the same way.
+/// `--record <path>` records the battle to a replay file.
+/// `--replay <path>` watches a recorded battle instead of simulating one.
+#[derive(Debug, Default)]
+struct Args {
+    headless: bool,
+    ticks: Option<u64>,
+    scenario: Option<String>,
+    seed: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--headless" => args.headless = true,
+                "--ticks" => {
+                    args.ticks = iter.next().and_then(|ticks| ticks.parse().ok());
+                    if args.ticks.is_none() {
+                        eprintln!("--ticks expects a number");
+                        std::process::exit(2);
+                    }
+                }
+                "--scenario" => {
+                    args.scenario = iter.next();
+                    if args.scenario.is_none() {
+                        eprintln!("--scenario expects a path");
+                        std::process::exit(2);
+                    }
+                }
+                "--record" => {
+                    args.record = iter.next();
+                    if args.record.is_none() {
+                        eprintln!("--record expects a path");
+                        std::process::exit(2);
+                    }
+                }
+                "--replay" => {
+                    args.replay = iter.next();
+                    if args.replay.is_none() {
+                        eprintln!("--replay expects a path");
+                        std::process::exit(2);
+                    }
+                }
+                "--seed" => {
+                    args.seed = iter.next().and_then(|seed| seed.parse().ok());
+                    if args.seed.is_none() {
+                        eprintln!("--seed expects a number");
+                        std::process::exit(2);
+                    }
+                }
+                other => {
+                    eprintln!("unknown argument: {other}");
+                    std::process::exit(2);
+                }
+            }
+        }
+        args
+    }
+}
+
 fn main() {
     color_backtrace::install();
-    App::new()
-        .add_plugins(EmbeddedAssetPlugin {
-            mode: PluginMode::ReplaceDefault,
-        })
-        .add_plugins((
+    build_app(Args::parse()).run();
+}
+
+fn build_app(args: Args) -> App {
+    let mut app = App::new();
+    #[cfg(not(feature = "hot_reload"))]
+    app.add_plugins(EmbeddedAssetPlugin {
+        mode: PluginMode::ReplaceDefault,
+    });
+    if let Some(scenario) = args.scenario {
+        app.insert_resource(ScenarioPath(scenario));
+    }
+    if let Some(seed) = args.seed {
+        app.insert_resource(SimSeed(seed));
+    }
+    if let Some(path) = args.record {
+        app.insert_resource(RecordReplay(path));
+    }
+    if let Some(path) = args.replay {
+        let replay = Replay::load(&path).unwrap_or_else(|error| {
+            eprintln!("couldn't load replay {path}: {error}");
+            std::process::exit(1);
+        });
+        app.insert_resource(ScenarioPath(replay.scenario.clone()))
+            .insert_resource(SimSeed(replay.seed))
+            .insert_resource(ReplayPlayback::new(replay));
+    }
+    if args.headless {
+        app.add_plugins((
+            MinimalPlugins,
+            LogPlugin::default(),
+            TransformPlugin,
+            HierarchyPlugin,
+            AssetPlugin::default(),
+            ScenePlugin,
+            StatesPlugin,
+            headless::plugin,
+        ))
+        // Avian's collider constructors read meshes even when nothing is rendered.
+        .init_asset::<Mesh>()
+        // One simulation tick per frame, so a headless run of n frames is n ticks of battle.
+        .insert_resource(TimeUpdateStrategy::ManualDuration(SIM_TICK))
+        .insert_resource(HeadlessRun::new(args.ticks));
+    } else {
+        app.add_plugins((
             DefaultPlugins
                 .set(WindowPlugin {
                     primary_window: Some(Window {
@@ -55,27 +203,53 @@ fn main() {
                     .into(),
                     ..default()
                 }),
-            PhysicsPlugins::default(),
             FpsOverlayPlugin::default(),
+            camera::plugin,
+            inspector::plugin,
+            vfx::plugin,
+            trails::plugin,
             // avian3d::prelude::PhysicsDebugPlugin::default(),
-            ships::plugin,
-            lasers::plugin,
-            lifetimes::plugin,
-            spawners::plugin,
-            capital_ships::plugin,
-            AutomaticUpdate::<TrackedByKDTree>::new()
-                .with_frequency(Duration::from_secs_f32(0.2))
-                .with_spatial_ds(bevy_spatial::SpatialStructure::KDTree3A),
         ))
-        .add_systems(Startup, setup)
-        .run();
+        .add_systems(Startup, setup_scenery);
+    }
+    app.add_plugins((
+        PhysicsPlugins::new(FixedPostUpdate),
+        health::plugin,
+        alliances::plugin,
+        ships::plugin,
+        ship_classes::plugin,
+        flocking::plugin,
+        lasers::plugin,
+        lifetimes::plugin,
+        spawners::plugin,
+        capital_ships::plugin,
+        match_state::plugin,
+        scenario::plugin,
+        targeting::plugin,
+        turrets::plugin,
+        AutomaticUpdate::<TrackedByKDTree>::new()
+            .with_frequency(Duration::ZERO)
+            .with_spatial_ds(bevy_spatial::SpatialStructure::KDTree3A)
+            .with_schedule(UpdateKDTree),
+    ))
+    .add_plugins((
+        asteroids::plugin,
+        simulation::plugin,
+        replay::plugin,
+        stats::plugin,
+    ))
+    .add_systems(
+        FixedPreUpdate,
+        update_kd_tree.run_if(every(Duration::from_secs_f32(0.2))),
+    )
+    // It's space.
+    .insert_resource(Gravity(Vec3::ZERO));
+    app
 }
 
-fn setup(
+/// Camera and overlay configuration, only needed when rendering.
+fn setup_scenery(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut ambient_light: ResMut<AmbientLight>,
     asset_server: ResMut<AssetServer>,
     mut fps_overlay_config: ResMut<FpsOverlayConfig>,
 ) {
@@ -84,7 +258,7 @@ fn setup(
         font_size: 16.,
         ..default()
     };
-    // camera
+    // camera, positioned by the scenario
     commands.spawn((
         Camera3d::default(),
         Bloom::NATURAL,
@@ -94,44 +268,49 @@ fn setup(
             ..Camera::default()
         },
         Transform::from_xyz(125.0, 45., 85.).looking_at(Vec3::ZERO, Vec3::Y),
+        CameraRig::default(),
     ));
-    ambient_light.brightness = 10.5;
-    // star
-    commands.spawn((
-        PointLight {
-            intensity: 600_200_000.,
-            range: 56000.,
-            shadows_enabled: true,
-            ..default()
-        },
-        Mesh3d(meshes.add(Sphere::new(4.0))),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::WHITE,
-            emissive: LinearRgba::rgb(15000.0, 15000.0, 10000.0),
-            ..default()
-        })),
-        Transform::from_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2))
-            .with_translation(vec3(-200., 50., -100.)),
-    ));
+}
 
-    let red_capital_ship_center = vec3(-80., -13., 35.);
-    commands.queue(SpawnCapitalShip {
-        transform: Transform::from_translation(red_capital_ship_center)
-            .with_rotation(Quat::from_axis_angle(Vec3::Y, 180.0_f32.to_radians())),
-        team: Team::Red,
-    });
-    let blue_capital_ship_center = vec3(80., 1., 0.);
-    commands.queue(SpawnCapitalShip {
-        transform: Transform::from_translation(blue_capital_ship_center),
-        team: Team::Blue,
-    });
+#[cfg(test)]
+mod tests {
+    use stats::BattleStats;
 
-    commands.spawn((
-        TeamTarget(Team::Red),
-        Transform::from_translation(blue_capital_ship_center),
-    ));
-    commands.spawn((
-        TeamTarget(Team::Blue),
-        Transform::from_translation(red_capital_ship_center),
-    ));
+    use super::*;
+
+    /// Runs a headless battle to the end, returning the stats and where every ship ended up.
+    fn run_battle(seed: u64, ticks: u64) -> (Vec<String>, Vec<[u32; 3]>) {
+        let mut app = build_app(Args {
+            headless: true,
+            seed: Some(seed),
+            ticks: Some(ticks),
+            ..default()
+        });
+        app.finish();
+        app.cleanup();
+        while app.should_exit().is_none() {
+            app.update();
+        }
+        let world = app.world_mut();
+        let stats = world.resource::<BattleStats>();
+        let mut summary: Vec<String> = Team::ALL
+            .into_iter()
+            .map(|team| format!("{team:?}: {:?}", stats.teams.get(&team)))
+            .collect();
+        summary.push(format!("{:?}", stats.kills));
+        let mut positions: Vec<[u32; 3]> = world
+            .query_filtered::<&Transform, With<Ship>>()
+            .iter(world)
+            .map(|transform| transform.translation.to_array().map(f32::to_bits))
+            .collect();
+        positions.sort_unstable();
+        (summary, positions)
+    }
+
+    #[test]
+    fn same_seed_plays_out_the_same() {
+        let first = run_battle(7, 900);
+        assert!(!first.1.is_empty(), "no fighters launched");
+        assert_eq!(first, run_battle(7, 900));
+    }
 }
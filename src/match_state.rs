@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, utils::HashSet};
+use serde::Deserialize;
+
+use crate::{alliances::Alliances, capital_ships::CapitalShip, health::Health, Team};
+
+/// How long the scenario sits idle before fighters start launching.
+const WARMUP: Duration = Duration::from_secs(3);
+/// How long fighters launch before victory conditions are checked.
+const DEPLOYING: Duration = Duration::from_secs(5);
+
+pub fn plugin(app: &mut App) {
+    app.init_state::<MatchState>();
+    app.register_type::<VictoryRule>();
+    app.init_resource::<VictoryRule>();
+    app.add_event::<RestartMatch>();
+    app.add_systems(OnEnter(MatchState::Warmup), start_match_timer);
+    app.add_systems(OnEnter(MatchState::Deploying), start_match_timer);
+    app.add_systems(
+        FixedUpdate,
+        (
+            // Warmup counts from when the scenario is spawned, however long it takes to load.
+            advance_after_timer(MatchState::Deploying)
+                .run_if(in_state(MatchState::Warmup))
+                .run_if(not(resource_exists::<PendingSetup>)),
+            advance_after_timer(MatchState::Battle).run_if(in_state(MatchState::Deploying)),
+            check_victory.run_if(in_state(MatchState::Battle)),
+            restart_match.run_if(on_event::<RestartMatch>),
+        ),
+    );
+    app.add_systems(
+        Update,
+        request_restart.run_if(resource_exists::<ButtonInput<KeyCode>>),
+    );
+    app.add_systems(
+        Startup,
+        setup_banner.run_if(resource_exists::<Assets<Font>>),
+    );
+    app.add_systems(Update, update_banner.run_if(state_changed::<MatchState>));
+}
+
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MatchState {
+    /// The scenario is being set up, nothing launches yet.
+    #[default]
+    Warmup,
+    /// Fighters are launching but the match can't be decided yet.
+    Deploying,
+    Battle,
+    Victory(Team),
+    Draw,
+}
+
+/// Run condition for systems that should only run while fighters are launching or fighting.
+pub fn match_in_progress(state: Res<State<MatchState>>) -> bool {
+    matches!(state.get(), MatchState::Deploying | MatchState::Battle)
+}
+
+/// How the winner of a match is decided.
+#[derive(Resource, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[reflect(Resource)]
+pub enum VictoryRule {
+    /// The last team with a capital ship wins.
+    #[default]
+    DestroyCapitalShips,
+    /// The last team with anything left alive wins.
+    LastTeamStanding,
+}
+
+/// Everything that belongs to a single match and is despawned when it restarts.
+#[derive(Component, Reflect, Default)]
+pub struct MatchEntity;
+
+/// Present while the scenario for the current match still needs to be spawned.
+#[derive(Resource, Default)]
+pub struct PendingSetup;
+
+/// Despawns everything in the current match and sets up the scenario again.
+#[derive(Event, Debug, Default)]
+pub struct RestartMatch;
+
+#[derive(Resource)]
+struct MatchTimer(Timer);
+
+fn start_match_timer(mut commands: Commands, state: Res<State<MatchState>>) {
+    let duration = match state.get() {
+        MatchState::Warmup => {
+            commands.init_resource::<PendingSetup>();
+            WARMUP
+        }
+        _ => DEPLOYING,
+    };
+    commands.insert_resource(MatchTimer(Timer::new(duration, TimerMode::Once)));
+}
+
+fn advance_after_timer(
+    next: MatchState,
+) -> impl FnMut(Option<ResMut<MatchTimer>>, ResMut<NextState<MatchState>>, Res<Time>) {
+    move |timer, mut next_state, time| {
+        let Some(mut timer) = timer else {
+            return;
+        };
+        if timer.0.tick(time.delta()).just_finished() {
+            next_state.set(next);
+        }
+    }
+}
+
+fn check_victory(
+    rule: Res<VictoryRule>,
+    alliances: Res<Alliances>,
+    capital_ships: Query<(&Team, &Health), With<CapitalShip>>,
+    everything: Query<(&Team, &Health)>,
+    mut next_state: ResMut<NextState<MatchState>>,
+) {
+    let alive: HashSet<Team> = match *rule {
+        VictoryRule::DestroyCapitalShips => capital_ships
+            .iter()
+            .filter(|(_, health)| !health.is_dead())
+            .map(|(team, _)| *team)
+            .collect(),
+        VictoryRule::LastTeamStanding => everything
+            .iter()
+            .filter(|(_, health)| !health.is_dead())
+            .map(|(team, _)| *team)
+            .collect(),
+    };
+    let Some(&survivor) = alive.iter().next() else {
+        next_state.set(MatchState::Draw);
+        return;
+    };
+    // An alliance wins together, the victory is credited to one of its surviving teams.
+    if alive
+        .iter()
+        .all(|&team| alliances.are_allied(survivor, team))
+    {
+        next_state.set(MatchState::Victory(survivor));
+    }
+}
+
+fn request_restart(keys: Res<ButtonInput<KeyCode>>, mut restart: EventWriter<RestartMatch>) {
+    if keys.just_pressed(KeyCode::KeyR) {
+        restart.send_default();
+    }
+}
+
+fn restart_match(
+    mut commands: Commands,
+    mut events: EventReader<RestartMatch>,
+    entities: Query<Entity, With<MatchEntity>>,
+    mut next_state: ResMut<NextState<MatchState>>,
+) {
+    events.clear();
+    for entity in entities.iter() {
+        if let Some(entity) = commands.get_entity(entity) {
+            entity.try_despawn_recursive();
+        }
+    }
+    // Set these directly as well, restarting during warmup doesn't re-enter the state.
+    commands.init_resource::<PendingSetup>();
+    commands.insert_resource(MatchTimer(Timer::new(WARMUP, TimerMode::Once)));
+    next_state.set(MatchState::Warmup);
+}
+
+#[derive(Component)]
+struct MatchBanner;
+
+fn setup_banner(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            top: Val::Px(24.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        })
+        .with_child((
+            Text::default(),
+            TextFont {
+                font: asset_server.load("IBM Plex Mono/IBMPlexMono-Regular.ttf"),
+                font_size: 24.,
+                ..default()
+            },
+            MatchBanner,
+        ));
+}
+
+fn update_banner(state: Res<State<MatchState>>, mut banner: Query<&mut Text, With<MatchBanner>>) {
+    let Ok(mut text) = banner.get_single_mut() else {
+        return;
+    };
+    text.0 = match state.get() {
+        MatchState::Warmup => "Warmup".to_string(),
+        MatchState::Deploying => "Deploying".to_string(),
+        MatchState::Battle => String::new(),
+        MatchState::Victory(team) => format!("{team:?} wins! Press R to restart"),
+        MatchState::Draw => "Draw! Press R to restart".to_string(),
+    };
+}
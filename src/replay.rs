@@ -0,0 +1,1095 @@
+//! Records battles to a compact binary file and plays them back later.
+//!
+//! A recording holds, for every simulation tick, what happened (spawns, despawns, hits, deaths)
+//! and where things moved to, keyed by [`ReplayId`]s that stay the same for the whole battle.
+//! To keep files small, transforms are only written every [`KEYFRAME_TICKS`] ticks and only for
+//! entities that moved, and lasers only have the transform they were fired with. Playback pauses
+//! the simulation and only moves meshes around, so neither the AI nor physics run.
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use thiserror::Error;
+
+use crate::{
+    asteroids::{Asteroid, SpawnAsteroid},
+    capital_ships::{CapitalShip, CapitalShipAssets, SpawnCapitalShip},
+    health::{DamageEvent, ShipDestroyed},
+    lasers::{Laser, LaserAssets, ProjectileKind},
+    scenario::{ScenarioPath, SpawnerLayout},
+    ship_classes::ShipClass,
+    simulation::{SimSeed, SIM_TICK},
+    turrets::Turret,
+    weapons::{Beam, Missile, Tracer},
+    Ship, ShipAssets, Team,
+};
+
+const MAGIC: &[u8; 4] = b"SBRP";
+const VERSION: u32 = 5;
+/// Ticks between recorded transforms, playback interpolates in between.
+const KEYFRAME_TICKS: usize = 4;
+/// Ticks per chunk of the playback index.
+const CHUNK_TICKS: usize = 64;
+/// Stands in for a missing team or entity in the file.
+const NONE: u32 = u32::MAX;
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<ReplayId>();
+    app.add_systems(
+        Startup,
+        start_recording.run_if(resource_exists::<RecordReplay>),
+    );
+    app.add_systems(FixedLast, record_tick.run_if(resource_exists::<Recorder>));
+    app.add_systems(
+        Startup,
+        (
+            pause_simulation,
+            setup_playback_text.run_if(resource_exists::<Assets<Font>>),
+        )
+            .run_if(resource_exists::<ReplayPlayback>),
+    );
+    app.add_systems(
+        Update,
+        (
+            control_playback.run_if(resource_exists::<ButtonInput<KeyCode>>),
+            advance_playback,
+            sync_playback,
+            update_playback_text,
+        )
+            .chain()
+            .run_if(resource_exists::<ReplayPlayback>),
+    );
+}
+
+/// Identifies an entity across a whole recording.
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReplayId(pub u32);
+
+/// What to spawn for a recorded entity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayKind {
+    Ship {
+        team: Team,
+        class: ShipClass,
+    },
+    /// Flies straight at `speed` from where it was fired, so only its first transform is recorded.
+    Laser {
+        team: Option<Team>,
+        kind: ProjectileKind,
+        speed: f32,
+    },
+    Missile {
+        team: Option<Team>,
+        kind: ProjectileKind,
+    },
+    CapitalShip {
+        team: Team,
+    },
+    Asteroid {
+        radius: f32,
+    },
+    /// Recorded apart from its capital ship so it can be destroyed on its own.
+    Turret {
+        team: Team,
+    },
+    /// A railgun tracer or beam, stretched to its recorded length.
+    Streak {
+        team: Option<Team>,
+        kind: ProjectileKind,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayEvent {
+    Spawn {
+        id: u32,
+        kind: ReplayKind,
+    },
+    Despawn {
+        id: u32,
+    },
+    Hit {
+        target: u32,
+        source: Option<u32>,
+        amount: f32,
+    },
+    Destroyed {
+        id: u32,
+        killer: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayTick {
+    pub events: Vec<ReplayEvent>,
+    /// Entities spawned this tick, and on keyframes those that moved since last written. Sorted
+    /// by id.
+    pub transforms: Vec<(u32, Transform)>,
+    /// How long each streak with a transform this tick is, sorted by id.
+    pub lengths: Vec<(u32, f32)>,
+}
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("couldn't read the replay: {0}")]
+    Io(#[from] io::Error),
+    #[error("not a replay file")]
+    NotAReplay,
+    #[error("replay version {0} isn't supported, expected {}", VERSION)]
+    UnsupportedVersion(u32),
+    #[error("the replay ends unexpectedly")]
+    Truncated,
+    #[error("the replay is corrupt: {0}")]
+    Corrupt(&'static str),
+}
+
+/// A recording loaded into memory.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub seed: u64,
+    /// Asset path of the scenario the battle was fought in.
+    pub scenario: String,
+    pub ticks: Vec<ReplayTick>,
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = ReplayReader { bytes };
+        if reader.take(4)? != MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let seed = reader.u64()?;
+        let scenario_len = reader.u32()? as usize;
+        let scenario = String::from_utf8(reader.take(scenario_len)?.to_vec())
+            .map_err(|_| ReplayError::Corrupt("scenario path isn't UTF-8"))?;
+        let mut ticks = Vec::new();
+        // A recording cut short mid-tick (e.g. the game crashed) still plays up to that point.
+        while !reader.bytes.is_empty() {
+            match reader.tick() {
+                Ok(tick) => ticks.push(tick),
+                Err(ReplayError::Truncated) => break,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(Self {
+            seed,
+            scenario,
+            ticks,
+        })
+    }
+}
+
+struct ReplayReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ReplayReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReplayError> {
+        if self.bytes.len() < len {
+            return Err(ReplayError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ReplayError> {
+        Ok(self.take(N)?.try_into().expect("took exactly N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ReplayError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, ReplayError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Result<f32, ReplayError> {
+        self.array().map(f32::from_le_bytes)
+    }
+
+    fn i16(&mut self) -> Result<i16, ReplayError> {
+        self.array().map(i16::from_le_bytes)
+    }
+
+    fn id(&mut self) -> Result<Option<u32>, ReplayError> {
+        self.u32().map(|id| (id != NONE).then_some(id))
+    }
+
+    fn team(&mut self) -> Result<Option<Team>, ReplayError> {
+        match self.u8()? {
+            u8::MAX => Ok(None),
+            index => Team::try_from(index as usize)
+                .map(Some)
+                .map_err(|_| ReplayError::Corrupt("unknown team")),
+        }
+    }
+
+    fn projectile_kind(&mut self) -> Result<ProjectileKind, ReplayError> {
+        ProjectileKind::ALL
+            .get(self.u8()? as usize)
+            .copied()
+            .ok_or(ReplayError::Corrupt("unknown projectile kind"))
+    }
+
+    fn kind(&mut self) -> Result<ReplayKind, ReplayError> {
+        let missing_team = || ReplayError::Corrupt("missing team");
+        Ok(match self.u8()? {
+            0 => ReplayKind::Ship {
+                team: self.team()?.ok_or_else(missing_team)?,
+                class: *ShipClass::ALL
+                    .get(self.u8()? as usize)
+                    .ok_or(ReplayError::Corrupt("unknown ship class"))?,
+            },
+            1 => ReplayKind::Laser {
+                team: self.team()?,
+                kind: self.projectile_kind()?,
+                speed: self.f32()?,
+            },
+            2 => ReplayKind::CapitalShip {
+                team: self.team()?.ok_or_else(missing_team)?,
+            },
+            3 => ReplayKind::Asteroid {
+                radius: self.f32()?,
+            },
+            4 => ReplayKind::Turret {
+                team: self.team()?.ok_or_else(missing_team)?,
+            },
+            5 => ReplayKind::Streak {
+                team: self.team()?,
+                kind: self.projectile_kind()?,
+            },
+            6 => ReplayKind::Missile {
+                team: self.team()?,
+                kind: self.projectile_kind()?,
+            },
+            _ => return Err(ReplayError::Corrupt("unknown entity kind")),
+        })
+    }
+
+    fn event(&mut self) -> Result<ReplayEvent, ReplayError> {
+        Ok(match self.u8()? {
+            0 => ReplayEvent::Spawn {
+                id: self.u32()?,
+                kind: self.kind()?,
+            },
+            1 => ReplayEvent::Despawn { id: self.u32()? },
+            2 => ReplayEvent::Hit {
+                target: self.u32()?,
+                source: self.id()?,
+                amount: self.f32()?,
+            },
+            3 => ReplayEvent::Destroyed {
+                id: self.u32()?,
+                killer: self.id()?,
+            },
+            _ => return Err(ReplayError::Corrupt("unknown event")),
+        })
+    }
+
+    fn tick(&mut self) -> Result<ReplayTick, ReplayError> {
+        let mut tick = ReplayTick::default();
+        for _ in 0..self.u32()? {
+            tick.events.push(self.event()?);
+        }
+        for _ in 0..self.u32()? {
+            let id = self.u32()?;
+            let translation = Vec3::new(self.f32()?, self.f32()?, self.f32()?);
+            let rotation = [self.i16()?, self.i16()?, self.i16()?, self.i16()?]
+                .map(|component| f32::from(component) / f32::from(i16::MAX));
+            tick.transforms.push((
+                id,
+                Transform::from_translation(translation)
+                    .with_rotation(Quat::from_array(rotation).normalize()),
+            ));
+        }
+        for _ in 0..self.u32()? {
+            tick.lengths.push((self.u32()?, self.f32()?));
+        }
+        Ok(tick)
+    }
+}
+
+fn write_team(writer: &mut impl Write, team: Option<Team>) -> io::Result<()> {
+    let index = team.and_then(|team| Team::ALL.iter().position(|&other| other == team));
+    writer.write_all(&[index.map_or(u8::MAX, |index| index as u8)])
+}
+
+fn write_id(writer: &mut impl Write, id: Option<u32>) -> io::Result<()> {
+    writer.write_all(&id.unwrap_or(NONE).to_le_bytes())
+}
+
+fn write_projectile_kind(writer: &mut impl Write, kind: ProjectileKind) -> io::Result<()> {
+    let kind = ProjectileKind::ALL.iter().position(|&other| other == kind);
+    writer.write_all(&[kind.unwrap_or_default() as u8])
+}
+
+fn write_kind(writer: &mut impl Write, kind: ReplayKind) -> io::Result<()> {
+    match kind {
+        ReplayKind::Ship { team, class } => {
+            writer.write_all(&[0])?;
+            write_team(writer, Some(team))?;
+            let class = ShipClass::ALL.iter().position(|&other| other == class);
+            writer.write_all(&[class.unwrap_or_default() as u8])
+        }
+        ReplayKind::Laser { team, kind, speed } => {
+            writer.write_all(&[1])?;
+            write_team(writer, team)?;
+            write_projectile_kind(writer, kind)?;
+            writer.write_all(&speed.to_le_bytes())
+        }
+        ReplayKind::CapitalShip { team } => {
+            writer.write_all(&[2])?;
+            write_team(writer, Some(team))
+        }
+        ReplayKind::Asteroid { radius } => {
+            writer.write_all(&[3])?;
+            writer.write_all(&radius.to_le_bytes())
+        }
+        ReplayKind::Turret { team } => {
+            writer.write_all(&[4])?;
+            write_team(writer, Some(team))
+        }
+        ReplayKind::Streak { team, kind } => {
+            writer.write_all(&[5])?;
+            write_team(writer, team)?;
+            write_projectile_kind(writer, kind)
+        }
+        ReplayKind::Missile { team, kind } => {
+            writer.write_all(&[6])?;
+            write_team(writer, team)?;
+            write_projectile_kind(writer, kind)
+        }
+    }
+}
+
+fn write_event(writer: &mut impl Write, event: ReplayEvent) -> io::Result<()> {
+    match event {
+        ReplayEvent::Spawn { id, kind } => {
+            writer.write_all(&[0])?;
+            writer.write_all(&id.to_le_bytes())?;
+            write_kind(writer, kind)
+        }
+        ReplayEvent::Despawn { id } => {
+            writer.write_all(&[1])?;
+            writer.write_all(&id.to_le_bytes())
+        }
+        ReplayEvent::Hit {
+            target,
+            source,
+            amount,
+        } => {
+            writer.write_all(&[2])?;
+            writer.write_all(&target.to_le_bytes())?;
+            write_id(writer, source)?;
+            writer.write_all(&amount.to_le_bytes())
+        }
+        ReplayEvent::Destroyed { id, killer } => {
+            writer.write_all(&[3])?;
+            writer.write_all(&id.to_le_bytes())?;
+            write_id(writer, killer)
+        }
+    }
+}
+
+fn write_header(writer: &mut impl Write, seed: SimSeed, scenario: &str) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&seed.0.to_le_bytes())?;
+    writer.write_all(&(scenario.len() as u32).to_le_bytes())?;
+    writer.write_all(scenario.as_bytes())
+}
+
+fn write_tick(writer: &mut impl Write, tick: &ReplayTick) -> io::Result<()> {
+    writer.write_all(&(tick.events.len() as u32).to_le_bytes())?;
+    for event in tick.events.iter() {
+        write_event(writer, *event)?;
+    }
+    writer.write_all(&(tick.transforms.len() as u32).to_le_bytes())?;
+    for (id, transform) in tick.transforms.iter() {
+        writer.write_all(&id.to_le_bytes())?;
+        for component in transform.translation.to_array() {
+            writer.write_all(&component.to_le_bytes())?;
+        }
+        // Rotations are unit quaternions, 16 bits per component is plenty.
+        for component in transform.rotation.normalize().to_array() {
+            let quantized = (component * f32::from(i16::MAX)).round() as i16;
+            writer.write_all(&quantized.to_le_bytes())?;
+        }
+    }
+    writer.write_all(&(tick.lengths.len() as u32).to_le_bytes())?;
+    for (id, length) in tick.lengths.iter() {
+        writer.write_all(&id.to_le_bytes())?;
+        writer.write_all(&length.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Where to record the battle to, set from the `--record` argument.
+#[derive(Resource, Debug, Clone)]
+pub struct RecordReplay(pub String);
+
+#[derive(Resource)]
+struct Recorder {
+    writer: BufWriter<File>,
+    ids: HashMap<Entity, u32>,
+    next_id: u32,
+    /// The last transform written for each recorded entity.
+    written: HashMap<u32, Transform>,
+    /// Ticks recorded so far.
+    tick: usize,
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(error) = self.writer.flush() {
+            error!("Couldn't finish writing the replay: {error}");
+        }
+    }
+}
+
+fn start_recording(
+    mut commands: Commands,
+    record: Res<RecordReplay>,
+    seed: Res<SimSeed>,
+    scenario: Res<ScenarioPath>,
+) {
+    let start = || -> io::Result<BufWriter<File>> {
+        let mut writer = BufWriter::new(File::create(&record.0)?);
+        write_header(&mut writer, *seed, &scenario.0)?;
+        Ok(writer)
+    };
+    match start() {
+        Ok(writer) => {
+            info!("Recording replay to {}", record.0);
+            commands.insert_resource(Recorder {
+                writer,
+                ids: HashMap::new(),
+                next_id: 0,
+                written: HashMap::new(),
+                tick: 0,
+            });
+        }
+        Err(error) => error!("Couldn't record replay to {}: {error}", record.0),
+    }
+}
+
+/// Turrets and beams are recorded where they are in the world, since playback spawns them on
+/// their own. `GlobalTransform` isn't propagated again until the start of the next tick.
+fn world_transform(
+    transform: &Transform,
+    parent: Option<&Parent>,
+    parents: &Query<(&Transform, Option<&Parent>)>,
+) -> Transform {
+    let mut world = *transform;
+    let mut parent = parent.map(Parent::get);
+    while let Some((transform, next)) = parent.and_then(|parent| parents.get(parent).ok()) {
+        world = transform.mul_transform(world);
+        parent = next.map(Parent::get);
+    }
+    world
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn record_tick(
+    mut commands: Commands,
+    mut recorder: ResMut<Recorder>,
+    new_ships: Query<(Entity, &Transform, &Team, &ShipClass), (With<Ship>, Without<ReplayId>)>,
+    new_lasers: Query<(Entity, &Transform, &Laser, Has<Missile>), Without<ReplayId>>,
+    new_capital_ships: Query<(Entity, &Transform, &Team), (With<CapitalShip>, Without<ReplayId>)>,
+    new_asteroids: Query<(Entity, &Transform, &Asteroid), Without<ReplayId>>,
+    new_turrets: Query<(Entity, &Transform, &Parent, &Team), (With<Turret>, Without<ReplayId>)>,
+    new_tracers: Query<(Entity, &Transform, &Tracer), Without<ReplayId>>,
+    new_beams: Query<(Entity, &Transform, &Parent, &Beam), Without<ReplayId>>,
+    tracked: Query<(
+        &ReplayId,
+        &Transform,
+        Option<&Parent>,
+        Has<Laser>,
+        Has<Missile>,
+        Has<Tracer>,
+        Has<Beam>,
+    )>,
+    parents: Query<(&Transform, Option<&Parent>)>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut destroyed: EventReader<ShipDestroyed>,
+) {
+    let mut tick = ReplayTick::default();
+    for event in damage_events.read() {
+        if let Some(&target) = recorder.ids.get(&event.target) {
+            tick.events.push(ReplayEvent::Hit {
+                target,
+                source: event
+                    .source
+                    .and_then(|source| recorder.ids.get(&source).copied()),
+                amount: event.amount,
+            });
+        }
+    }
+    for event in destroyed.read() {
+        if let Some(&id) = recorder.ids.get(&event.entity) {
+            tick.events.push(ReplayEvent::Destroyed {
+                id,
+                killer: event
+                    .killer
+                    .and_then(|killer| recorder.ids.get(&killer).copied()),
+            });
+        }
+    }
+    // `RemovedComponents` is cleared every frame rather than every tick, so despawns would be
+    // missed on frames without a tick. Anything recorded that's gone now was despawned since the
+    // last tick.
+    let mut despawned = Vec::new();
+    recorder.ids.retain(|&entity, &mut id| {
+        let alive = tracked.contains(entity);
+        if !alive {
+            despawned.push(id);
+        }
+        alive
+    });
+    despawned.sort_unstable();
+    for id in despawned {
+        recorder.written.remove(&id);
+        tick.events.push(ReplayEvent::Despawn { id });
+    }
+    if recorder.tick % KEYFRAME_TICKS == 0 {
+        for (id, transform, parent, is_laser, is_missile, is_tracer, is_beam) in tracked.iter() {
+            // Playback works out where lasers are from where they were fired.
+            if is_laser && !is_missile {
+                continue;
+            }
+            let transform = world_transform(transform, parent, &parents);
+            if recorder.written.get(&id.0) == Some(&transform) {
+                continue;
+            }
+            recorder.written.insert(id.0, transform);
+            tick.transforms.push((id.0, transform));
+            if is_tracer || is_beam {
+                tick.lengths.push((id.0, transform.scale.z));
+            }
+        }
+    }
+
+    let spawned = new_ships
+        .iter()
+        .map(|(entity, transform, &team, &class)| {
+            (entity, *transform, ReplayKind::Ship { team, class })
+        })
+        .chain(
+            new_lasers
+                .iter()
+                .map(|(entity, transform, laser, is_missile)| {
+                    let kind = if is_missile {
+                        ReplayKind::Missile {
+                            team: laser.team,
+                            kind: laser.kind,
+                        }
+                    } else {
+                        ReplayKind::Laser {
+                            team: laser.team,
+                            kind: laser.kind,
+                            speed: laser.speed,
+                        }
+                    };
+                    (entity, *transform, kind)
+                }),
+        )
+        .chain(new_capital_ships.iter().map(|(entity, transform, &team)| {
+            (entity, *transform, ReplayKind::CapitalShip { team })
+        }))
+        .chain(new_asteroids.iter().map(|(entity, transform, asteroid)| {
+            (
+                entity,
+                *transform,
+                ReplayKind::Asteroid {
+                    radius: asteroid.radius,
+                },
+            )
+        }))
+        .chain(
+            new_turrets
+                .iter()
+                .map(|(entity, transform, parent, &team)| {
+                    (
+                        entity,
+                        world_transform(transform, Some(parent), &parents),
+                        ReplayKind::Turret { team },
+                    )
+                }),
+        )
+        .chain(new_tracers.iter().map(|(entity, transform, tracer)| {
+            (
+                entity,
+                *transform,
+                ReplayKind::Streak {
+                    team: tracer.team,
+                    kind: tracer.kind,
+                },
+            )
+        }))
+        .chain(new_beams.iter().map(|(entity, transform, parent, beam)| {
+            (
+                entity,
+                world_transform(transform, Some(parent), &parents),
+                ReplayKind::Streak {
+                    team: beam.team,
+                    kind: beam.kind,
+                },
+            )
+        }));
+    for (entity, transform, kind) in spawned {
+        let id = recorder.next_id;
+        recorder.next_id += 1;
+        recorder.ids.insert(entity, id);
+        commands.entity(entity).insert(ReplayId(id));
+        tick.events.push(ReplayEvent::Spawn { id, kind });
+        recorder.written.insert(id, transform);
+        tick.transforms.push((id, transform));
+        if matches!(kind, ReplayKind::Streak { .. }) {
+            tick.lengths.push((id, transform.scale.z));
+        }
+    }
+    tick.transforms.sort_unstable_by_key(|(id, _)| *id);
+    tick.lengths.sort_unstable_by_key(|(id, _)| *id);
+    recorder.tick += 1;
+
+    if let Err(error) = write_tick(&mut recorder.writer, &tick) {
+        error!("Couldn't write to the replay, recording stopped: {error}");
+        commands.remove_resource::<Recorder>();
+    }
+}
+
+/// When a recorded entity exists, in ticks, and where it was.
+#[derive(Debug, Clone)]
+struct Lifetime {
+    id: u32,
+    kind: ReplayKind,
+    spawned: usize,
+    despawned: Option<usize>,
+    /// Every transform written for the entity and the tick it was written on, streaks stretched
+    /// to their length.
+    keyframes: Vec<(usize, Transform)>,
+}
+
+impl Lifetime {
+    fn alive_at(&self, tick: usize) -> bool {
+        self.spawned <= tick && self.despawned.is_none_or(|despawned| tick < despawned)
+    }
+
+    /// Where the entity is at `cursor`, interpolated between the keyframes either side of it.
+    fn transform_at(&self, cursor: f64) -> Option<Transform> {
+        if let ReplayKind::Laser { speed, .. } = self.kind {
+            let &(tick, fired) = self.keyframes.first()?;
+            let distance = speed * ((cursor - tick as f64) * SIM_TICK.as_secs_f64()) as f32;
+            return Some(fired.with_translation(fired.translation + fired.forward() * distance));
+        }
+        let next = self
+            .keyframes
+            .partition_point(|&(tick, _)| tick as f64 <= cursor);
+        let &(from_tick, from) = self.keyframes.get(next.checked_sub(1)?)?;
+        let Some(&(to_tick, to)) = self.keyframes.get(next) else {
+            return Some(from);
+        };
+        // Nothing is written while an entity stands still, so a gap longer than a keyframe means
+        // it only started moving after the keyframe before `to`.
+        let start = from_tick.max(to_tick.saturating_sub(KEYFRAME_TICKS));
+        let fraction = ((cursor - start as f64) / (to_tick - start) as f64).clamp(0.0, 1.0) as f32;
+        Some(Transform {
+            translation: from.translation.lerp(to.translation, fraction),
+            rotation: from.rotation.slerp(to.rotation, fraction),
+            scale: from.scale.lerp(to.scale, fraction),
+        })
+    }
+}
+
+/// A replay being watched, loaded from the `--replay` argument.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    lifetimes: Vec<Lifetime>,
+    /// Indices of the lifetimes overlapping each [`CHUNK_TICKS`] ticks, so playback only looks
+    /// at entities that could be alive around the cursor.
+    chunks: Vec<Vec<usize>>,
+    /// Current position in ticks, fractional between recorded ticks.
+    cursor: f64,
+    /// Playback speed relative to the original battle.
+    speed: f64,
+    paused: bool,
+    /// Entities spawned for each lifetime that's currently alive.
+    spawned: HashMap<usize, Entity>,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        let mut lifetimes = Vec::new();
+        let mut index_of = HashMap::new();
+        for (tick_index, tick) in replay.ticks.iter().enumerate() {
+            for event in tick.events.iter() {
+                match *event {
+                    ReplayEvent::Spawn { id, kind } => {
+                        index_of.insert(id, lifetimes.len());
+                        lifetimes.push(Lifetime {
+                            id,
+                            kind,
+                            spawned: tick_index,
+                            despawned: None,
+                            keyframes: Vec::new(),
+                        });
+                    }
+                    ReplayEvent::Despawn { id } => {
+                        if let Some(&index) = index_of.get(&id) {
+                            lifetimes[index].despawned = Some(tick_index);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            for &(id, mut transform) in tick.transforms.iter() {
+                let Some(&index) = index_of.get(&id) else {
+                    continue;
+                };
+                if let Ok(length) = tick.lengths.binary_search_by_key(&id, |(id, _)| *id) {
+                    transform.scale.z = tick.lengths[length].1;
+                }
+                lifetimes[index].keyframes.push((tick_index, transform));
+            }
+        }
+        let mut chunks = vec![Vec::new(); replay.ticks.len() / CHUNK_TICKS + 1];
+        for (index, lifetime) in lifetimes.iter().enumerate() {
+            let last = lifetime
+                .despawned
+                .map_or(replay.ticks.len(), |despawned| despawned.saturating_sub(1))
+                .max(lifetime.spawned);
+            for chunk in &mut chunks[lifetime.spawned / CHUNK_TICKS..=last / CHUNK_TICKS] {
+                chunk.push(index);
+            }
+        }
+        Self {
+            replay,
+            lifetimes,
+            chunks,
+            cursor: 0.0,
+            speed: 1.0,
+            paused: false,
+            spawned: HashMap::new(),
+        }
+    }
+
+    fn last_tick(&self) -> f64 {
+        self.replay.ticks.len().saturating_sub(1) as f64
+    }
+}
+
+/// Stops the simulation so nothing but the replay moves.
+fn pause_simulation(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+/// Space pauses, left and right skip 5 seconds, up and down change speed, Home restarts.
+fn control_playback(keys: Res<ButtonInput<KeyCode>>, mut playback: ResMut<ReplayPlayback>) {
+    let skip = 5.0 / SIM_TICK.as_secs_f64();
+    if keys.just_pressed(KeyCode::Space) {
+        playback.paused = !playback.paused;
+    }
+    if keys.just_pressed(KeyCode::ArrowRight) {
+        playback.cursor += skip;
+    }
+    if keys.just_pressed(KeyCode::ArrowLeft) {
+        playback.cursor -= skip;
+    }
+    if keys.just_pressed(KeyCode::Home) {
+        playback.cursor = 0.0;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        playback.speed = (playback.speed * 2.0).min(16.0);
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        playback.speed = (playback.speed / 2.0).max(0.125);
+    }
+    playback.cursor = playback.cursor.clamp(0.0, playback.last_tick());
+}
+
+fn advance_playback(mut playback: ResMut<ReplayPlayback>, time: Res<Time<Real>>) {
+    if playback.paused {
+        return;
+    }
+    let advance = time.delta_secs_f64() / SIM_TICK.as_secs_f64() * playback.speed;
+    playback.cursor = (playback.cursor + advance).min(playback.last_tick());
+}
+
+/// Spawns and despawns entities to match the replay at the cursor, and moves them into place.
+fn sync_playback(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut transforms: Query<&mut Transform>,
+    ship_assets: Res<ShipAssets>,
+    capital_ship_assets: Res<CapitalShipAssets>,
+    laser_assets: Option<Res<LaserAssets>>,
+) {
+    let ReplayPlayback {
+        lifetimes,
+        chunks,
+        cursor,
+        spawned,
+        ..
+    } = &mut *playback;
+    let tick = cursor.floor() as usize;
+
+    spawned.retain(|&index, &mut entity| {
+        let alive = lifetimes[index].alive_at(tick);
+        if !alive {
+            commands.entity(entity).try_despawn_recursive();
+        }
+        alive
+    });
+    let Some(chunk) = chunks.get(tick / CHUNK_TICKS) else {
+        return;
+    };
+    for &index in chunk.iter() {
+        let lifetime = &lifetimes[index];
+        if !lifetime.alive_at(tick) {
+            continue;
+        }
+        let Some(transform) = lifetime.transform_at(*cursor) else {
+            continue;
+        };
+        if let Some(&entity) = spawned.get(&index) {
+            if let Ok(mut current) = transforms.get_mut(entity) {
+                *current = transform;
+            }
+            continue;
+        }
+        let mut entity = commands.spawn(ReplayId(lifetime.id));
+        match lifetime.kind {
+            ReplayKind::Ship { team, class } => {
+                entity.insert((transform, Visibility::Visible));
+                if let (Some(mesh), Some(material)) = (
+                    ship_assets.meshes.get(&class),
+                    ship_assets.materials.get(&team),
+                ) {
+                    entity.insert((Mesh3d(mesh.clone()), MeshMaterial3d(material.clone())));
+                }
+            }
+            ReplayKind::Laser { team, kind, .. } | ReplayKind::Missile { team, kind } => {
+                entity.insert((transform, Visibility::Visible));
+                if let Some(visuals) = laser_assets
+                    .as_ref()
+                    .and_then(|assets| assets.visuals(kind, team))
+                {
+                    entity.insert(visuals);
+                }
+            }
+            ReplayKind::Streak { team, kind } => {
+                entity.insert((transform, Visibility::Visible));
+                if let Some(visuals) = laser_assets
+                    .as_ref()
+                    .and_then(|assets| assets.streak_visuals(kind, team))
+                {
+                    entity.insert(visuals);
+                }
+            }
+            ReplayKind::CapitalShip { team } => {
+                entity
+                    .queue(SpawnCapitalShip {
+                        transform,
+                        team,
+                        spawners: SpawnerLayout {
+                            decks: 0,
+                            ..default()
+                        },
+                        // Turrets are recorded, and destroyed, separately.
+                        turrets: false,
+                    })
+                    // The fly-in animation would fight the recorded transforms.
+                    .remove::<AnimationPlayer>();
+            }
+            ReplayKind::Asteroid { radius } => {
+                entity.queue(SpawnAsteroid {
+                    position: transform.translation,
+                    radius,
+                    velocity: Vec3::ZERO,
+                    spin: Vec3::ZERO,
+                    destructible: false,
+                    min_split_radius: radius,
+                    shape_seed: u64::from(lifetime.id),
+                });
+            }
+            ReplayKind::Turret { team } => {
+                entity.insert((transform, Visibility::Visible));
+                if let (Some(mesh), Some(material)) = (
+                    capital_ship_assets.turret.as_ref(),
+                    ship_assets.materials.get(&team),
+                ) {
+                    entity.insert((Mesh3d(mesh.clone()), MeshMaterial3d(material.clone())));
+                }
+            }
+        }
+        spawned.insert(index, entity.id());
+    }
+}
+
+#[derive(Component)]
+struct PlaybackText;
+
+fn setup_playback_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(24.0),
+            bottom: Val::Px(24.0),
+            ..default()
+        })
+        .with_child((
+            Text::default(),
+            TextFont {
+                font: asset_server.load("IBM Plex Mono/IBMPlexMono-Regular.ttf"),
+                font_size: 16.,
+                ..default()
+            },
+            PlaybackText,
+        ));
+}
+
+fn update_playback_text(
+    playback: Res<ReplayPlayback>,
+    mut text: Query<&mut Text, With<PlaybackText>>,
+) {
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let tick_secs = SIM_TICK.as_secs_f64();
+    text.0 = format!(
+        "\
+        Replay {:.1}s / {:.1}s  x{}{}\n\
+        Space pause, ←/→ skip, ↑/↓ speed, Home restart\
+        ",
+        playback.cursor * tick_secs,
+        playback.last_tick() * tick_secs,
+        playback.speed,
+        if playback.paused { "  [paused]" } else { "" },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::DEFAULT_SCENARIO;
+
+    fn example_tick() -> ReplayTick {
+        ReplayTick {
+            events: vec![
+                ReplayEvent::Spawn {
+                    id: 0,
+                    kind: ReplayKind::Ship {
+                        team: Team::Blue,
+                        class: ShipClass::Bomber,
+                    },
+                },
+                ReplayEvent::Spawn {
+                    id: 1,
+                    kind: ReplayKind::Laser {
+                        team: None,
+                        kind: ProjectileKind::ALL[0],
+                        speed: 35.0,
+                    },
+                },
+                ReplayEvent::Spawn {
+                    id: 2,
+                    kind: ReplayKind::Streak {
+                        team: Some(Team::Red),
+                        kind: ProjectileKind::ALL[0],
+                    },
+                },
+                ReplayEvent::Hit {
+                    target: 0,
+                    source: None,
+                    amount: 2.5,
+                },
+                ReplayEvent::Destroyed {
+                    id: 0,
+                    killer: Some(2),
+                },
+                ReplayEvent::Despawn { id: 3 },
+            ],
+            transforms: vec![
+                (0, Transform::from_xyz(1.0, -2.0, 3.5)),
+                (
+                    2,
+                    Transform::from_xyz(0.0, 4.0, 0.0).with_rotation(Quat::from_rotation_y(1.0)),
+                ),
+            ],
+            lengths: vec![(2, 12.0)],
+        }
+    }
+
+    fn example_file(ticks: &[ReplayTick]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_header(&mut bytes, SimSeed(42), DEFAULT_SCENARIO).unwrap();
+        for tick in ticks {
+            write_tick(&mut bytes, tick).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn tick_round_trip() {
+        let tick = example_tick();
+        let mut bytes = Vec::new();
+        write_tick(&mut bytes, &tick).unwrap();
+        let mut reader = ReplayReader { bytes: &bytes };
+        let read = reader.tick().unwrap();
+        assert!(reader.bytes.is_empty());
+        assert_eq!(read.events, tick.events);
+        assert_eq!(read.lengths, tick.lengths);
+        assert_eq!(read.transforms.len(), tick.transforms.len());
+        for ((read_id, read), (id, transform)) in read.transforms.iter().zip(&tick.transforms) {
+            assert_eq!(read_id, id);
+            assert_eq!(read.translation, transform.translation);
+            // Rotations are quantized.
+            assert!(read.rotation.abs_diff_eq(transform.rotation, 1e-4));
+        }
+    }
+
+    #[test]
+    fn truncated_replay_plays_up_to_the_cut() {
+        let ticks = [example_tick(), ReplayTick::default(), example_tick()];
+        let bytes = example_file(&ticks);
+        let replay = Replay::from_bytes(&bytes).unwrap();
+        assert_eq!(replay.seed, 42);
+        assert_eq!(replay.scenario, DEFAULT_SCENARIO);
+        assert_eq!(replay.ticks.len(), 3);
+
+        let cut = Replay::from_bytes(&bytes[..bytes.len() - 5]).unwrap();
+        assert_eq!(cut.ticks.len(), 2);
+        assert_eq!(cut.ticks[1], ReplayTick::default());
+    }
+
+    #[test]
+    fn truncated_header_is_an_error() {
+        let bytes = example_file(&[]);
+        assert!(matches!(
+            Replay::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ReplayError::Truncated)
+        ));
+        assert!(matches!(
+            Replay::from_bytes(b"nope"),
+            Err(ReplayError::NotAReplay)
+        ));
+    }
+}
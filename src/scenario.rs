@@ -0,0 +1,356 @@
+//! Battles are described by `.scenario.ron` files in `assets/scenarios`.
+use std::time::Duration;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext, LoadState},
+    prelude::*,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    alliances::Alliances,
+    asteroids::{AsteroidFieldSpec, SpawnAsteroidField},
+    camera::CameraRig,
+    capital_ships::SpawnCapitalShip,
+    flocking::SquadronLayout,
+    lasers::FriendlyFire,
+    match_state::{MatchEntity, MatchState, PendingSetup, RestartMatch, VictoryRule},
+    replay::ReplayPlayback,
+    ship_classes::ShipClass,
+    simulation::{SimRng, SimSeed, SimTick},
+    Obstacle, Team, TeamTarget,
+};
+
+pub const DEFAULT_SCENARIO: &str = "scenarios/default.scenario.ron";
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<ScenarioPath>();
+    app.init_asset::<Scenario>();
+    app.init_asset_loader::<ScenarioLoader>();
+    app.add_systems(Startup, load_scenario);
+    app.add_systems(
+        FixedUpdate,
+        spawn_scenario
+            .run_if(in_state(MatchState::Warmup))
+            .run_if(resource_exists::<PendingSetup>),
+    );
+    // Replays pause the simulation, so their scenery is set up outside of the tick.
+    app.add_systems(
+        Update,
+        (
+            spawn_scenario
+                .run_if(resource_exists::<ReplayPlayback>)
+                .run_if(resource_exists::<PendingSetup>),
+            restart_on_change,
+            exit_on_load_failure.run_if(resource_exists::<CurrentScenario>),
+        ),
+    );
+}
+
+/// Asset path of the scenario to run, set from the `--scenario` argument.
+#[derive(Resource, Debug, Clone)]
+pub struct ScenarioPath(pub String);
+
+impl Default for ScenarioPath {
+    fn default() -> Self {
+        Self(DEFAULT_SCENARIO.to_string())
+    }
+}
+
+#[derive(Resource)]
+pub struct CurrentScenario(pub Handle<Scenario>);
+
+#[derive(Asset, TypePath, Deserialize, Debug, Clone)]
+pub struct Scenario {
+    #[serde(default)]
+    pub victory_rule: VictoryRule,
+    #[serde(default)]
+    pub friendly_fire: FriendlyFire,
+    /// Groups of teams fighting on the same side, everyone else is hostile.
+    #[serde(default)]
+    pub alliances: Alliances,
+    pub camera: CameraSpec,
+    #[serde(default)]
+    pub ambient_light: f32,
+    #[serde(default)]
+    pub lights: Vec<LightSpec>,
+    #[serde(default)]
+    pub capital_ships: Vec<CapitalShipSpec>,
+    #[serde(default)]
+    pub targets: Vec<TargetSpec>,
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleSpec>,
+    #[serde(default)]
+    pub asteroid_fields: Vec<AsteroidFieldSpec>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CameraSpec {
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+}
+
+/// A point light, optionally drawn as a glowing sphere (e.g. a star).
+#[derive(Deserialize, Debug, Clone)]
+pub struct LightSpec {
+    pub position: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    #[serde(default = "white")]
+    pub color: [f32; 3],
+    #[serde(default)]
+    pub radius: Option<f32>,
+}
+
+fn white() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CapitalShipSpec {
+    pub team: Team,
+    pub position: [f32; 3],
+    /// Rotation around the up axis.
+    #[serde(default)]
+    pub yaw_degrees: f32,
+    #[serde(default)]
+    pub spawners: SpawnerLayout,
+}
+
+/// Fighter launch bays along both sides of a capital ship.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SpawnerLayout {
+    /// Rows of bays stacked vertically.
+    pub decks: u32,
+    /// Bays per deck on each side of the ship.
+    pub bays: u32,
+    /// Fighters each bay launches, unlimited if `None`.
+    pub max_per_bay: Option<usize>,
+    /// Seconds between launches from a single bay.
+    pub delay: f32,
+    /// Fighter classes launched and their relative weights.
+    pub classes: Vec<(ShipClass, f32)>,
+    /// Launch fighters in formation, each bay forming its own squadrons.
+    pub squadron: Option<SquadronLayout>,
+}
+
+impl Default for SpawnerLayout {
+    fn default() -> Self {
+        Self {
+            decks: 2,
+            bays: 7,
+            max_per_bay: Some(200),
+            delay: 0.2,
+            classes: vec![
+                (ShipClass::Interceptor, 3.0),
+                (ShipClass::HeavyFighter, 5.0),
+                (ShipClass::Bomber, 2.0),
+                (ShipClass::Corvette, 0.2),
+            ],
+            squadron: None,
+        }
+    }
+}
+
+impl SpawnerLayout {
+    pub fn delay(&self) -> Duration {
+        Duration::from_secs_f32(self.delay)
+    }
+}
+
+/// A sphere fighters have to fly around.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ObstacleSpec {
+    pub position: [f32; 3],
+    pub radius: f32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TargetSpec {
+    /// The team that should attack this point.
+    pub team: Team,
+    pub position: [f32; 3],
+}
+
+#[derive(Default)]
+struct ScenarioLoader;
+
+#[derive(Debug, Error)]
+enum ScenarioLoaderError {
+    #[error("could not read scenario: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse scenario: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for ScenarioLoader {
+    type Asset = Scenario;
+    type Settings = ();
+    type Error = ScenarioLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Scenario, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["scenario.ron"]
+    }
+}
+
+fn load_scenario(mut commands: Commands, asset_server: Res<AssetServer>, path: Res<ScenarioPath>) {
+    commands.insert_resource(CurrentScenario(asset_server.load(path.0.clone())));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_scenario(
+    mut commands: Commands,
+    current: Option<Res<CurrentScenario>>,
+    scenarios: Res<Assets<Scenario>>,
+    mut cameras: Query<(&mut Transform, Option<&mut CameraRig>), With<Camera3d>>,
+    ambient_light: Option<ResMut<AmbientLight>>,
+    mut meshes: Option<ResMut<Assets<Mesh>>>,
+    mut materials: Option<ResMut<Assets<StandardMaterial>>>,
+    mut victory_rule: ResMut<VictoryRule>,
+    mut friendly_fire: ResMut<FriendlyFire>,
+    mut alliances: ResMut<Alliances>,
+    seed: Res<SimSeed>,
+    mut tick: ResMut<SimTick>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    let Some(scenario) = current.and_then(|current| scenarios.get(&current.0)) else {
+        return;
+    };
+    commands.remove_resource::<PendingSetup>();
+    // Every match starts from the same random state and tick, so a restart replays the battle.
+    commands.insert_resource(SimRng::new(*seed));
+    *tick = SimTick::default();
+    *victory_rule = scenario.victory_rule;
+    *friendly_fire = scenario.friendly_fire;
+    *alliances = scenario.alliances.clone();
+
+    let camera = &scenario.camera;
+    for (mut transform, rig) in cameras.iter_mut() {
+        *transform = Transform::from_translation(camera.position.into())
+            .looking_at(camera.look_at.into(), Vec3::Y);
+        if let Some(mut rig) = rig {
+            rig.place(camera.position.into(), camera.look_at.into());
+        }
+    }
+    if let Some(mut ambient_light) = ambient_light {
+        ambient_light.brightness = scenario.ambient_light;
+    }
+
+    for light in scenario.lights.iter() {
+        let [r, g, b] = light.color;
+        let mut entity = commands.spawn((
+            MatchEntity,
+            PointLight {
+                intensity: light.intensity,
+                range: light.range,
+                color: Color::linear_rgb(r, g, b),
+                shadows_enabled: true,
+                ..default()
+            },
+            Transform::from_translation(light.position.into()),
+        ));
+        if let (Some(radius), Some(meshes), Some(materials)) =
+            (light.radius, meshes.as_mut(), materials.as_mut())
+        {
+            entity.insert((
+                Mesh3d(meshes.add(Sphere::new(radius))),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color: Color::WHITE,
+                    emissive: LinearRgba::rgb(r, g, b) * 15000.0,
+                    ..default()
+                })),
+            ));
+        }
+    }
+
+    // When watching a replay, everything that fights or moves comes from the recording.
+    let spawn_combatants = playback.is_none();
+
+    for capital_ship in scenario.capital_ships.iter().filter(|_| spawn_combatants) {
+        commands.spawn_empty().queue(SpawnCapitalShip {
+            transform: Transform::from_translation(capital_ship.position.into())
+                .with_rotation(Quat::from_rotation_y(capital_ship.yaw_degrees.to_radians())),
+            team: capital_ship.team,
+            spawners: capital_ship.spawners.clone(),
+            turrets: true,
+        });
+    }
+
+    for target in scenario.targets.iter().filter(|_| spawn_combatants) {
+        commands.spawn((
+            TeamTarget(target.team),
+            Transform::from_translation(target.position.into()),
+        ));
+    }
+
+    for obstacle in scenario.obstacles.iter() {
+        let mut entity = commands.spawn((
+            Obstacle {
+                radius: obstacle.radius,
+            },
+            Transform::from_translation(obstacle.position.into()),
+        ));
+        if let (Some(meshes), Some(materials)) = (meshes.as_mut(), materials.as_mut()) {
+            entity.insert((
+                Mesh3d(meshes.add(Sphere::new(obstacle.radius))),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color: Color::srgb(0.3, 0.3, 0.35),
+                    perceptual_roughness: 0.9,
+                    ..default()
+                })),
+            ));
+        }
+    }
+
+    for field in scenario.asteroid_fields.iter().filter(|_| spawn_combatants) {
+        commands.queue(SpawnAsteroidField(field.clone()));
+    }
+}
+
+/// Stops the app if the scenario can't be loaded, otherwise the match would wait in warmup forever.
+fn exit_on_load_failure(
+    current: Res<CurrentScenario>,
+    scenarios: Res<Assets<Scenario>>,
+    asset_server: Res<AssetServer>,
+    path: Res<ScenarioPath>,
+    mut exit: EventWriter<AppExit>,
+) {
+    // A broken edit while hot reloading keeps the last version of the scenario around.
+    if scenarios.contains(&current.0) {
+        return;
+    }
+    if let LoadState::Failed(error) = asset_server.load_state(&current.0) {
+        error!("Couldn't load scenario {}: {error}", path.0);
+        exit.send(AppExit::error());
+    }
+}
+
+/// Restarts the match whenever the running scenario file is edited.
+fn restart_on_change(
+    mut events: EventReader<AssetEvent<Scenario>>,
+    current: Option<Res<CurrentScenario>>,
+    mut restart: EventWriter<RestartMatch>,
+) {
+    let Some(current) = current else {
+        return;
+    };
+    for event in events.read() {
+        if event.is_modified(&current.0) {
+            info!("Scenario changed, restarting the match");
+            restart.send_default();
+        }
+    }
+}
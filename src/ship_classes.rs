@@ -0,0 +1,236 @@
+use bevy::{
+    math::vec3,
+    prelude::*,
+    render::mesh::{ConeMeshBuilder, CylinderMeshBuilder},
+};
+use serde::Deserialize;
+
+use crate::{
+    lasers::{Gun, ProjectileKind},
+    weapons::Weapon,
+};
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<ShipClass>();
+    app.register_type::<Hardpoint>();
+}
+
+#[derive(Component, Reflect, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[reflect(Component)]
+pub enum ShipClass {
+    /// Fast and nimble, but fragile with light guns.
+    Interceptor,
+    /// The all-rounder.
+    #[default]
+    HeavyFighter,
+    /// Slow with a single hard hitting gun.
+    Bomber,
+    /// A small warship with shields and guns covering every side.
+    Corvette,
+}
+
+/// A gun mounted on a ship, spawned as a child entity.
+///
+/// Lasers fired from a hardpoint belong to the ship it's mounted on.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+#[require(Transform)]
+pub struct Hardpoint;
+
+/// Gun placement on a ship, relative to the ship's center.
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub transform: Transform,
+    pub gun: Gun,
+}
+
+/// Everything that differs between [`ShipClass`]es.
+#[derive(Debug, Clone)]
+pub struct ShipClassStats {
+    /// Cruising speed in units per second.
+    pub speed: f32,
+    pub thrust: f32,
+    /// Degrees per second.
+    pub turn_rate: f32,
+    pub mass: f32,
+    pub health: f32,
+    /// Shield strength and regeneration per second.
+    pub shield: Option<(f32, f32)>,
+    pub collider_radius: f32,
+    pub loadout: Vec<Mount>,
+}
+
+impl ShipClass {
+    pub const ALL: [Self; 4] = [
+        Self::Interceptor,
+        Self::HeavyFighter,
+        Self::Bomber,
+        Self::Corvette,
+    ];
+
+    pub fn stats(self) -> ShipClassStats {
+        match self {
+            ShipClass::Interceptor => ShipClassStats {
+                speed: 22.0,
+                thrust: 60.0,
+                turn_rate: 70.0,
+                mass: 0.8,
+                health: 1.5,
+                shield: None,
+                collider_radius: 0.4,
+                loadout: mirrored(
+                    vec3(0.3, 0.0, -0.4),
+                    Gun {
+                        range: 30.0,
+                        damage: 0.75,
+                        cooldown: 1.2,
+                        ..default()
+                    },
+                ),
+            },
+            ShipClass::HeavyFighter => ShipClassStats {
+                speed: 15.0,
+                thrust: 40.0,
+                turn_rate: 40.0,
+                mass: 1.5,
+                health: 3.0,
+                shield: Some((1.0, 0.25)),
+                collider_radius: 0.5,
+                loadout: mirrored(vec3(0.7, 0.0, -0.3), Gun::default()),
+            },
+            ShipClass::Bomber => ShipClassStats {
+                speed: 11.0,
+                thrust: 30.0,
+                turn_rate: 30.0,
+                mass: 2.5,
+                health: 4.0,
+                shield: Some((2.0, 0.5)),
+                collider_radius: 0.7,
+                loadout: [Mount {
+                    transform: Transform::from_xyz(0.0, -0.3, -0.8),
+                    gun: Gun {
+                        range: 50.0,
+                        damage: 4.0,
+                        arc: 6.0,
+                        cooldown: 4.0,
+                        burst: 1,
+                        projectile: ProjectileKind::HeavyLaser,
+                        ..default()
+                    },
+                }]
+                .into_iter()
+                .chain(mirrored(
+                    vec3(0.6, -0.2, 0.0),
+                    Gun {
+                        range: 70.0,
+                        damage: 3.0,
+                        arc: 30.0,
+                        cooldown: 8.0,
+                        burst: 1,
+                        weapon: Weapon::Missile {
+                            speed: 20.0,
+                            turn_rate: 90.0,
+                            fuel: 4.0,
+                        },
+                        projectile: ProjectileKind::Missile,
+                        ..default()
+                    },
+                ))
+                .collect(),
+            },
+            ShipClass::Corvette => {
+                let gun = Gun {
+                    range: 50.0,
+                    arc: 25.0,
+                    cooldown: 1.5,
+                    burst: 3,
+                    weapon: Weapon::Flak {
+                        speed: 30.0,
+                        radius: 2.5,
+                    },
+                    projectile: ProjectileKind::Flak,
+                    // Escorts screen the fleet from missiles.
+                    point_defense: true,
+                    ..default()
+                };
+                ShipClassStats {
+                    speed: 8.0,
+                    thrust: 40.0,
+                    turn_rate: 20.0,
+                    mass: 10.0,
+                    health: 20.0,
+                    shield: Some((10.0, 1.0)),
+                    collider_radius: 1.5,
+                    loadout: [0.0_f32, 90.0, 180.0, 270.0]
+                        .into_iter()
+                        .map(|angle| {
+                            let rotation = Quat::from_rotation_y(angle.to_radians());
+                            Mount {
+                                transform: Transform::from_translation(
+                                    rotation * vec3(0.0, 0.8, -1.0),
+                                )
+                                .with_rotation(rotation),
+                                gun: gun.clone(),
+                            }
+                        })
+                        .collect(),
+                }
+            }
+        }
+    }
+
+    pub fn mesh(self) -> Mesh {
+        match self {
+            ShipClass::Interceptor => {
+                let mut mesh = fighter_mesh();
+                mesh.transform_by(Transform::from_scale(vec3(0.6, 0.6, 0.9)));
+                mesh
+            }
+            ShipClass::HeavyFighter => fighter_mesh(),
+            ShipClass::Bomber => {
+                let mut mesh = fighter_mesh();
+                mesh.transform_by(Transform::from_scale(vec3(1.2, 1.3, 1.1)));
+                mesh
+            }
+            ShipClass::Corvette => {
+                let mut hull = CylinderMeshBuilder::new(0.8, 3.5, 8).build();
+                hull.transform_by(Transform::from_rotation(Quat::from_rotation_x(
+                    90.0_f32.to_radians(),
+                )));
+                let mut nose = fighter_mesh();
+                nose.transform_by(Transform::from_xyz(0.0, 0.0, -1.5));
+                hull.merge(&nose);
+                hull
+            }
+        }
+    }
+}
+
+/// A pair of identical guns on either side of the ship.
+fn mirrored(offset: Vec3, gun: Gun) -> Vec<Mount> {
+    [1.0, -1.0]
+        .into_iter()
+        .map(|side| Mount {
+            transform: Transform::from_translation(offset * vec3(side, 1.0, 1.0)),
+            gun: gun.clone(),
+        })
+        .collect()
+}
+
+/// The original fighter: a cone with a smaller cone on each side.
+fn fighter_mesh() -> Mesh {
+    let mut cone_mesh = ConeMeshBuilder::new(0.5, 2.0, 32).build();
+    cone_mesh.transform_by(Transform::from_rotation(Quat::from_rotation_x(
+        -90.0_f32.to_radians(),
+    )));
+    let mut left_cone = cone_mesh.clone();
+    left_cone.transform_by(
+        Transform::from_translation(vec3(0.7, 0.0, 0.0)).with_scale(Vec3::splat(0.6)),
+    );
+    let mut right_cone = cone_mesh.clone();
+    right_cone.transform_by(
+        Transform::from_translation(vec3(-0.7, 0.0, 0.0)).with_scale(Vec3::splat(0.6)),
+    );
+    cone_mesh.merge(&left_cone);
+    cone_mesh.merge(&right_cone);
+    cone_mesh
+}
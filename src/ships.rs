@@ -1,39 +1,61 @@
-use std::time::Duration;
-
-use avian3d::prelude::Collider;
+use avian3d::prelude::{
+    AngularVelocity, Collider, CollisionLayers, ExternalForce, ExternalImpulse, LayerMask,
+    LinearVelocity, Mass, PhysicsLayer, RigidBody, ShapeCastConfig, SpatialQuery,
+    SpatialQueryFilter,
+};
 use bevy::{
-    math::vec3, prelude::*, render::mesh::ConeMeshBuilder, time::common_conditions::on_timer,
+    ecs::{component::ComponentId, system::EntityCommand, world::DeferredWorld},
+    prelude::*,
     utils::HashMap,
 };
-use bevy_spatial::{kdtree::KDTree3A, SpatialAccess};
+use serde::Deserialize;
 
 use crate::{
-    lasers::{Gun, Laser},
+    capital_ships::Hull,
+    flocking::{Flocking, Squadron},
+    health::{Health, Kills, Shield},
+    lasers::{Gun, LASER_SPEED},
+    match_state::MatchEntity,
+    ship_classes::{Hardpoint, ShipClass},
+    targeting::{lead_position, CurrentTarget, SensorRange},
     TrackedByKDTree,
 };
 
+/// How fast fighters fly, in units per second.
+pub const SHIP_SPEED: f32 = 15.0;
+
 pub fn plugin(app: &mut App) {
     app.register_type::<Ship>();
     app.register_type::<Team>();
+    app.register_type::<Thrusters>();
+    app.register_type::<Steering>();
+    app.register_type::<Obstacle>();
     app.add_systems(PreStartup, setup);
     app.add_systems(
-        Update,
+        FixedUpdate,
         (
-            move_ships,
-            rotate_towards_target,
-            rotate_away_from_obstacles,
-            update_ship_count.run_if(on_timer(Duration::from_secs(1))),
+            clear_steering.before(SteeringSystems),
+            (steer_towards_target, avoid_obstacles).in_set(SteeringSystems),
+            fly_ships.after(SteeringSystems),
         ),
     );
 }
 
+/// Behaviours that add to a ship's [`Steering`] each frame, before [`fly_ships`] acts on it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SteeringSystems;
+
 fn setup(
     mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    asset_server: ResMut<AssetServer>,
+    materials: Option<ResMut<Assets<StandardMaterial>>>,
+    meshes: Option<ResMut<Assets<Mesh>>>,
 ) {
     let mut assets = ShipAssets::default();
+    // Headless runs have no renderer, ships are spawned without meshes.
+    let (Some(mut materials), Some(mut meshes)) = (materials, meshes) else {
+        commands.insert_resource(assets);
+        return;
+    };
     for &team in Team::ALL.iter() {
         assets.materials.insert(
             team,
@@ -43,104 +65,86 @@ fn setup(
                 ..default()
             }),
         );
-        let mut cone_mesh = ConeMeshBuilder::new(0.5, 2.0, 32).build();
-        cone_mesh.transform_by(Transform::from_rotation(Quat::from_rotation_x(
-            -90.0_f32.to_radians(),
-        )));
-        let mut left_cone = cone_mesh.clone();
-        left_cone.transform_by(
-            Transform::from_translation(vec3(0.7, 0.0, 0.0)).with_scale(Vec3::splat(0.6)),
-        );
-        let mut right_cone = cone_mesh.clone();
-        right_cone.transform_by(
-            Transform::from_translation(vec3(-0.7, 0.0, 0.0)).with_scale(Vec3::splat(0.6)),
-        );
-        cone_mesh.merge(&left_cone);
-        cone_mesh.merge(&right_cone);
-        assets.mesh.insert(team, meshes.add(cone_mesh));
+    }
+    for class in ShipClass::ALL {
+        assets.meshes.insert(class, meshes.add(class.mesh()));
     }
     commands.insert_resource(assets);
-    commands
-        .spawn(Node {
-            margin: UiRect {
-                left: Val::Px(24.0),
-                top: Val::Px(48.0),
-                ..default()
-            },
-            ..default()
-        })
-        .with_child((
-            Text::default(),
-            TextFont {
-                font: asset_server.load("IBM Plex Mono/IBMPlexMono-Regular.ttf"),
-                font_size: 16.,
-                ..default()
-            },
-            StatsText,
-        ));
-}
-
-#[derive(Component)]
-struct StatsText;
-
-fn update_ship_count(
-    mut text: Query<&mut Text, With<StatsText>>,
-    ships: Query<Entity, With<Ship>>,
-    lasers: Query<Entity, With<Laser>>,
-    entities: Query<Entity>,
-) {
-    let ship_count = ships.iter().len();
-    let laser_count = lasers.iter().len();
-    let mut text = text.single_mut();
-    let entity_count = entities.iter().len();
-    text.0 = format!(
-        "\
-        Entities: {entity_count}\n\
-        ├ Ships: {ship_count}\n\
-        └ Lasers: {laser_count}\n\
-        "
-    );
 }
 
+/// Turns an empty entity into a ship, e.g. `commands.spawn_empty().queue(SpawnShip { .. })`.
 pub struct SpawnShip {
     pub transform: Transform,
     pub team: Team,
+    pub class: ShipClass,
+    pub squadron: Option<Squadron>,
 }
 
-impl Command for SpawnShip {
-    fn apply(self, world: &mut World) {
+impl EntityCommand for SpawnShip {
+    fn apply(self, entity: Entity, world: &mut World) {
         let ship_assets = world
             .get_resource::<ShipAssets>()
             .expect("ship_assets resource was missing");
-        world.spawn((
-            MeshMaterial3d(
-                ship_assets
-                    .materials
-                    .get(&self.team)
-                    .expect("ship_assets should be initialized")
-                    .clone(),
-            ),
-            Mesh3d(
-                ship_assets
-                    .mesh
-                    .get(&self.team)
-                    .expect("ship_assets should be initialized")
-                    .clone(),
-            ),
-            Collider::sphere(0.5),
+        let material = ship_assets.materials.get(&self.team).cloned();
+        let mesh = ship_assets.meshes.get(&self.class).cloned();
+        let stats = self.class.stats();
+        let mut ship = world.entity_mut(entity);
+        ship.insert((
+            Ship,
+            self.class,
+            Thrusters {
+                thrust: stats.thrust,
+                max_speed: stats.speed,
+                turn_rate: stats.turn_rate,
+            },
+            Mass(stats.mass),
+            Health::new(stats.health),
+            LinearVelocity(self.transform.forward() * stats.speed),
+            Collider::sphere(stats.collider_radius),
+            self.team.collision_layers(),
             self.transform,
             Visibility::Visible,
             self.team,
         ));
+        if let Some(squadron) = self.squadron {
+            ship.insert(squadron);
+        }
+        if let Some((max, regen_rate)) = stats.shield {
+            ship.insert(Shield::new(max, regen_rate));
+        }
+        if let (Some(material), Some(mesh)) = (material, mesh) {
+            ship.insert((MeshMaterial3d(material), Mesh3d(mesh)));
+        }
+        ship.with_children(|children| {
+            for mount in stats.loadout {
+                children.spawn((Hardpoint, mount.gun, mount.transform));
+            }
+        });
     }
 }
 
 #[derive(Reflect, Component, Default)]
-#[require(Transform, Visibility, Gun, TrackedByKDTree)]
+#[require(
+    Transform,
+    Visibility,
+    ShipClass,
+    TrackedByKDTree,
+    MatchEntity,
+    SensorRange,
+    Health(|| Health::new(2.0)),
+    RigidBody(|| RigidBody::Dynamic),
+    Mass(|| Mass(1.0)),
+    Thrusters,
+    Steering,
+    Flocking,
+    ExternalForce,
+    ExternalImpulse,
+    Kills
+)]
 pub struct Ship;
 
-#[derive(Debug, Copy, Clone, Component, Reflect, Default, Hash, Eq, PartialEq)]
-#[require(Ship)]
+#[derive(Debug, Copy, Clone, Component, Reflect, Default, Hash, Eq, PartialEq, Deserialize)]
+#[reflect(Component)]
 pub enum Team {
     #[default]
     Red,
@@ -150,7 +154,34 @@ pub enum Team {
 }
 
 impl Team {
-    const ALL: [Self; 4] = [Self::Red, Self::Blue, Self::Green, Self::Yellow];
+    pub const ALL: [Self; 4] = [Self::Red, Self::Blue, Self::Green, Self::Yellow];
+
+    pub fn layer(self) -> GameLayer {
+        match self {
+            Team::Red => GameLayer::Red,
+            Team::Blue => GameLayer::Blue,
+            Team::Green => GameLayer::Green,
+            Team::Yellow => GameLayer::Yellow,
+        }
+    }
+
+    /// Collision layers for colliders belonging to this team.
+    pub fn collision_layers(self) -> CollisionLayers {
+        CollisionLayers::new(self.layer(), LayerMask::ALL)
+    }
+}
+
+/// Physics layers, each team gets its own so spatial queries can cheaply skip allies.
+#[derive(PhysicsLayer, Debug, Clone, Copy, Default)]
+pub enum GameLayer {
+    #[default]
+    Default,
+    Red,
+    Blue,
+    Green,
+    Yellow,
+    /// Anything fighters should steer around rather than fly into.
+    Obstacle,
 }
 
 impl From<Team> for Color {
@@ -180,123 +211,215 @@ impl TryFrom<usize> for Team {
 
 /// A priority target for the given team to attack
 #[derive(Component, Reflect, Default)]
+#[require(MatchEntity)]
 pub struct TeamTarget(pub Team);
 
+/// A sphere fighters steer around, e.g. a space station or a big rock.
 #[derive(Component, Reflect, Default)]
+#[component(on_insert = obstacle_on_insert)]
+#[require(Transform, Visibility, MatchEntity, RigidBody(|| RigidBody::Static))]
 pub struct Obstacle {
     pub radius: f32,
 }
 
+fn obstacle_on_insert(mut world: DeferredWorld, entity: Entity, _id: ComponentId) {
+    let Some(radius) = world
+        .get::<Obstacle>(entity)
+        .map(|obstacle| obstacle.radius)
+    else {
+        return;
+    };
+    world.commands().entity(entity).insert((
+        Collider::sphere(radius),
+        CollisionLayers::new(GameLayer::Obstacle, LayerMask::ALL),
+    ));
+}
+
 #[derive(Resource, Reflect, Clone, Default)]
 pub struct ShipAssets {
     pub materials: HashMap<Team, Handle<StandardMaterial>>,
-    pub mesh: HashMap<Team, Handle<Mesh>>,
+    pub meshes: HashMap<ShipClass, Handle<Mesh>>,
 }
 
-fn move_ships(mut ships: Query<&mut Transform, With<Ship>>, time: Res<Time>) {
-    for mut transform in ships.iter_mut() {
-        let forward = transform.forward();
-        transform.translation += forward * 15.0 * time.delta_secs();
+/// Engine parameters used by [`fly_ships`] to chase the heading requested in [`Steering`].
+#[derive(Component, Reflect, Debug, Clone)]
+pub struct Thrusters {
+    /// Maximum force the engines can produce.
+    pub thrust: f32,
+    /// Speed the ship tries to cruise at, in units per second.
+    pub max_speed: f32,
+    /// Maximum turn rate in degrees per second.
+    pub turn_rate: f32,
+}
+
+impl Default for Thrusters {
+    fn default() -> Self {
+        Self {
+            thrust: 40.0,
+            max_speed: SHIP_SPEED,
+            turn_rate: 40.0,
+        }
     }
 }
 
-pub fn rotate_towards_target(
-    mut ships: Query<(&mut Transform, &GlobalTransform, &Team), With<Ship>>,
-    targets: Query<(&GlobalTransform, &TeamTarget)>,
+/// The direction a ship wants to fly in this frame, built up by the steering behaviours.
+///
+/// Each behaviour adds a weighted direction, [`fly_ships`] turns towards the sum.
+#[derive(Component, Reflect, Debug, Clone, Copy, Default)]
+pub struct Steering(pub Vec3);
+
+fn clear_steering(mut ships: Query<&mut Steering>) {
+    for mut steering in ships.iter_mut() {
+        steering.0 = Vec3::ZERO;
+    }
+}
+
+/// Turns ships towards their [`Steering`] heading and thrusts towards cruising speed.
+#[allow(clippy::type_complexity)]
+fn fly_ships(
+    mut ships: Query<(
+        &GlobalTransform,
+        &Steering,
+        &Thrusters,
+        &Mass,
+        &LinearVelocity,
+        &mut AngularVelocity,
+        &mut ExternalForce,
+    )>,
     time: Res<Time>,
+) {
+    let delta = time.delta_secs().max(f32::EPSILON);
+    ships.par_iter_mut().for_each(
+        |(transform, steering, thrusters, mass, velocity, mut angular_velocity, mut force)| {
+            let forward = transform.forward();
+            let heading = steering.0.try_normalize().unwrap_or(*forward);
+
+            let axis = forward.cross(heading);
+            let angle = forward.angle_between(heading);
+            angular_velocity.0 = match axis.try_normalize() {
+                Some(axis) => axis * (angle / delta).min(thrusters.turn_rate.to_radians()),
+                None => Vec3::ZERO,
+            };
+
+            // Accelerate towards cruising speed along the nose, this also bleeds off any sideways
+            // drift from collisions and hits.
+            let desired = forward * thrusters.max_speed;
+            let wanted_force = (desired - velocity.0) * mass.0 / delta;
+            force.set_force(wanted_force.clamp_length_max(thrusters.thrust));
+        },
+    );
+}
+
+/// Steers ships towards their [`CurrentTarget`], leading it so lasers connect, or towards their
+/// team's nearest [`TeamTarget`] when there's nothing in sensor range.
+pub fn steer_towards_target(
+    mut ships: Query<
+        (
+            &GlobalTransform,
+            &Team,
+            Option<&CurrentTarget>,
+            Option<&Children>,
+            &mut Steering,
+        ),
+        With<Ship>,
+    >,
+    targets: Query<(&GlobalTransform, &TeamTarget)>,
+    engaged: Query<(&GlobalTransform, Option<&LinearVelocity>)>,
+    guns: Query<&Gun, With<Hardpoint>>,
 ) {
     let targets: Vec<_> = targets.into_iter().collect();
 
-    ships
-        .par_iter_mut()
-        .for_each(|(mut transform, global_transform, team)| {
-            let global_transform = global_transform.compute_transform();
-            let target = targets.iter().fold(
-                None::<(f32, Vec3)>,
-                |previous, (next_transform, next_target)| {
-                    if next_target.0 != *team {
-                        return previous;
-                    }
-                    let pos = global_transform.translation;
-                    let next_pos = next_transform.translation();
-                    let dist = next_pos.distance_squared(pos);
-                    let Some(previous) = previous else {
-                        return Some((dist, next_pos));
-                    };
-                    if dist < previous.0 {
-                        Some((dist, next_pos))
-                    } else {
-                        Some(previous)
-                    }
-                },
-            );
-            let Some((_, target)) = target else {
+    ships.par_iter_mut().for_each(
+        |(global_transform, team, current_target, children, mut steering)| {
+            let position = global_transform.translation();
+            let engaged = current_target.and_then(|target| engaged.get(target.0).ok());
+            if let Some((target_transform, target_velocity)) = engaged {
+                // Lead for the main gun, the first in the loadout.
+                let projectile_speed = children
+                    .and_then(|children| guns.iter_many(children).next())
+                    .map_or(LASER_SPEED, |gun| gun.weapon.projectile_speed());
+                let aim = lead_position(
+                    position,
+                    target_transform.translation(),
+                    target_velocity.map_or(Vec3::ZERO, |velocity| velocity.0),
+                    projectile_speed,
+                );
+                steering.0 += (aim - position).normalize_or_zero();
                 return;
-            };
-            let look = global_transform.looking_at(target, Vec3::Y).rotation;
-            transform.rotation = transform
-                .rotation
-                .rotate_towards(look, 40.0_f32.to_radians() * time.delta_secs());
-        });
+            }
+            let target = targets
+                .iter()
+                .filter(|(_, target)| target.0 == *team)
+                .map(|(transform, _)| transform.translation())
+                .min_by(|a, b| {
+                    a.distance_squared(position)
+                        .total_cmp(&b.distance_squared(position))
+                });
+            if let Some(target) = target {
+                steering.0 += (target - position).normalize_or_zero();
+            }
+        },
+    );
 }
 
-pub fn rotate_away_from_obstacles(
-    mut ships: Query<(&mut Transform, &GlobalTransform), With<Ship>>,
-    tree: Res<KDTree3A<TrackedByKDTree>>,
-    time: Res<Time>,
+/// Seconds of flight ships look ahead for obstacles, on top of their turning circle.
+const OBSTACLE_LOOKAHEAD: f32 = 1.5;
+
+/// Sweeps each ship's collider ahead along its velocity and steers away from any obstacle or
+/// capital ship hull in the way.
+///
+/// Ships look further ahead the faster they fly and the wider they turn, so large obstacles are
+/// avoided early enough to actually get around them.
+fn avoid_obstacles(
+    mut ships: Query<
+        (
+            &GlobalTransform,
+            &LinearVelocity,
+            &Collider,
+            &Thrusters,
+            &mut Steering,
+        ),
+        With<Ship>,
+    >,
+    structures: Query<(), Or<(With<Obstacle>, With<Hull>)>>,
+    spatial_query: SpatialQuery,
 ) {
+    // Hulls stay on their team's layer so friendly fire filtering still skips them, which means
+    // fighters, turrets and missiles on those layers have to be skipped here instead.
+    let filter = SpatialQueryFilter::from_mask(
+        Team::ALL
+            .into_iter()
+            .fold(LayerMask::from(GameLayer::Obstacle), |mask, team| {
+                mask | LayerMask::from(team.layer())
+            }),
+    );
     ships
         .par_iter_mut()
-        .for_each(|(mut transform, global_transform)| {
-            let translation = global_transform.translation();
-            let Some((other_pos, _)) = tree.nearest_neighbour(global_transform.translation_vec3a())
-            else {
+        .for_each(|(transform, velocity, collider, thrusters, mut steering)| {
+            let speed = velocity.length();
+            let Ok(direction) = Dir3::new(velocity.0) else {
                 return;
             };
-
-            // Create a target direction pointing the ship away from the other entity.
-            let target_direction = Vec3::from(other_pos) - translation;
-            let distance = target_direction.length();
-            if !(0.1..=2.0).contains(&distance) {
+            let turning_radius = speed / thrusters.turn_rate.to_radians();
+            let lookahead = speed * OBSTACLE_LOOKAHEAD + turning_radius * 2.0;
+            let position = transform.translation();
+            let Some(hit) = spatial_query.cast_shape_predicate(
+                collider,
+                position,
+                Quat::IDENTITY,
+                direction,
+                &ShapeCastConfig::from_max_distance(lookahead),
+                &filter,
+                &|entity| structures.contains(entity),
+            ) else {
                 return;
+            };
+            // Slide along the obstacle's surface, picking a side if we're heading straight at it.
+            let mut away = hit.normal1.reject_from(*direction);
+            if away.length_squared() < 0.01 {
+                away = transform.up().reject_from(*direction);
             }
-            let target_direction = target_direction.normalize();
-            // println!("{:?} {:?}", transform.rotation, target_direction);
-            transform.rotation = rotate_towards(
-                transform.rotation,
-                target_direction,
-                100.0 * time.delta_secs(),
-            );
+            let urgency = 1.0 - (hit.point1.distance(position) / lookahead).min(1.0);
+            steering.0 += away.normalize_or_zero() * urgency * 6.0;
         });
 }
-
-fn rotate_towards(quat: Quat, target_dir: Vec3, angle_deg: f32) -> Quat {
-    // Ensure the target direction is normalized
-    let target_dir = target_dir.normalize();
-
-    // Compute the current direction (transform the Z-axis vector by the quaternion)
-    let current_dir = quat * Vec3::Z;
-
-    // Calculate the axis of rotation using the cross product
-    let rotation_axis = current_dir.cross(target_dir);
-
-    // If the cross product is zero, the vectors are parallel, no rotation needed
-    if rotation_axis.length_squared() < f32::EPSILON {
-        return quat.normalize();
-    }
-
-    // Normalize the rotation axis
-    let rotation_axis = rotation_axis.normalize();
-
-    // Convert angle from degrees to radians
-    let angle_rad = angle_deg.to_radians();
-
-    // Create a quaternion representing the rotation by `angle_rad` around the `rotation_axis`
-    let rotation_quat = Quat::from_axis_angle(rotation_axis, angle_rad);
-
-    // Combine the current quaternion with the rotation quaternion
-    let result_quat = rotation_quat * quat;
-
-    // Normalize the result to ensure it remains a valid rotation quaternion
-    result_quat.normalize()
-}
@@ -0,0 +1,90 @@
+//! Keeps battles reproducible: gameplay runs in [`FixedUpdate`] at a fixed tick, systems run in
+//! the same order every tick, and all randomness comes from [`SimRng`] seeded by [`SimSeed`].
+//!
+//! Anything that changes the course of a match, spawning the scenario, restarting and state
+//! transitions included, happens on a tick too, so battles play out the same at any frame rate.
+//!
+//! [`GlobalTransform`] is propagated at the start of every tick rather than once a frame, so
+//! gameplay reading it sees where everything was at the end of the previous tick.
+use std::time::Duration;
+
+use bevy::{
+    ecs::schedule::ExecutorKind,
+    prelude::*,
+    state::state::StateTransition,
+    transform::systems::{propagate_transforms, sync_simple_transforms},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Length of one simulation tick.
+pub const SIM_TICK: Duration = Duration::from_micros(16_667);
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<SimSeed>();
+    app.init_resource::<SimTick>();
+    app.add_systems(PreStartup, seed_rng);
+    app.insert_resource(Time::<Fixed>::from_duration(SIM_TICK));
+    // The multi-threaded executor picks the order of unrelated systems at runtime, which is
+    // enough to change the outcome of a battle. Systems still parallelise internally.
+    app.edit_schedule(FixedUpdate, |schedule| {
+        schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+    });
+    app.add_systems(
+        FixedFirst,
+        (
+            apply_state_transitions,
+            // Bevy only does this in `PostUpdate`, after however many ticks ran this frame.
+            (sync_simple_transforms, propagate_transforms),
+        ),
+    );
+    app.add_systems(FixedLast, advance_tick);
+}
+
+/// Seed for the match, set with `--seed`. Random unless given, but always logged so an
+/// interesting battle can be replayed.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct SimSeed(pub u64);
+
+impl Default for SimSeed {
+    fn default() -> Self {
+        Self(rand::thread_rng().gen())
+    }
+}
+
+/// The only source of randomness gameplay code should use.
+#[derive(Resource, Debug, Clone, Deref, DerefMut)]
+pub struct SimRng(pub StdRng);
+
+impl SimRng {
+    pub fn new(seed: SimSeed) -> Self {
+        Self(StdRng::seed_from_u64(seed.0))
+    }
+}
+
+/// Ticks since the scenario of the current match was spawned.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct SimTick(pub u64);
+
+/// Run condition for systems that only run every `period`. Unlike `on_timer` it counts from the
+/// start of the match, so they run on the same ticks however long the scenario took to load.
+pub fn every(period: Duration) -> impl FnMut(Res<SimTick>) -> bool + Clone {
+    let ticks = (period.as_secs_f64() / SIM_TICK.as_secs_f64())
+        .round()
+        .max(1.0) as u64;
+    move |tick: Res<SimTick>| tick.0 % ticks == 0
+}
+
+fn advance_tick(mut tick: ResMut<SimTick>) {
+    tick.0 += 1;
+}
+
+/// Bevy only applies [`NextState`] once a frame, which may be several ticks later when the frame
+/// rate is low. Applying it here as well means a state set during a tick is in effect by the next.
+fn apply_state_transitions(world: &mut World) {
+    let _ = world.try_run_schedule(StateTransition);
+}
+
+fn seed_rng(mut commands: Commands, seed: Res<SimSeed>) {
+    info!("Simulation seed: {}", seed.0);
+    commands.insert_resource(SimRng::new(*seed));
+}
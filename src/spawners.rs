@@ -1,11 +1,18 @@
 use std::time::Duration;
 
-use crate::{SpawnShip, Team};
+use crate::{
+    flocking::{Squadron, SquadronLayout},
+    match_state::match_in_progress,
+    ship_classes::ShipClass,
+    simulation::SimRng,
+    Ship, SpawnShip, Team,
+};
 use bevy::prelude::*;
+use rand::{distributions::WeightedIndex, prelude::Distribution};
 
 pub fn plugin(app: &mut App) {
     app.register_type::<Spawner>();
-    app.add_systems(Update, spawn);
+    app.add_systems(FixedUpdate, spawn.run_if(match_in_progress));
 }
 
 #[derive(Component, Reflect, Default)]
@@ -15,11 +22,28 @@ pub struct Spawner {
     pub team: Team,
     pub last_spawn: Option<f64>,
     pub spawned: usize,
+    /// Classes to launch and their relative weights, heavy fighters only if empty.
+    pub classes: Vec<(ShipClass, f32)>,
+    /// Launch ships in squadrons of the same class rather than one by one.
+    pub squadron: Option<SquadronLayout>,
+    /// The leader and class of the squadron currently being launched, and the next free slot.
+    pub forming: Option<(Entity, ShipClass, usize)>,
+}
+
+impl Spawner {
+    fn pick_class(&self, rng: &mut SimRng) -> ShipClass {
+        let Ok(weights) = WeightedIndex::new(self.classes.iter().map(|(_, weight)| *weight)) else {
+            return ShipClass::default();
+        };
+        self.classes[weights.sample(&mut rng.0)].0
+    }
 }
 
 fn spawn(
     mut commands: Commands,
     mut query: Query<(&GlobalTransform, &mut Spawner)>,
+    ships: Query<(), With<Ship>>,
+    mut rng: ResMut<SimRng>,
     time: Res<Time>,
 ) {
     for (transform, mut spawner) in query.iter_mut() {
@@ -33,9 +57,33 @@ fn spawn(
         {
             spawner.last_spawn = time.elapsed_secs_f64().into();
             spawner.spawned += 1;
-            commands.queue(SpawnShip {
+            let ship = commands.spawn_empty().id();
+            let mut class = spawner.pick_class(&mut rng);
+            let mut squadron = None;
+            if let Some(layout) = spawner.squadron.clone() {
+                match spawner.forming {
+                    // Wingmen join the leader until the squadron is full, or the leader is lost
+                    // before it's complete.
+                    Some((leader, leader_class, slot))
+                        if slot < layout.size && ships.contains(leader) =>
+                    {
+                        class = leader_class;
+                        squadron = Some(Squadron {
+                            leader,
+                            slot,
+                            formation: layout.formation,
+                            spacing: layout.spacing,
+                        });
+                        spawner.forming = Some((leader, leader_class, slot + 1));
+                    }
+                    _ => spawner.forming = Some((ship, class, 1)),
+                }
+            }
+            commands.entity(ship).queue(SpawnShip {
                 transform: transform.compute_transform(),
                 team: spawner.team,
+                class,
+                squadron,
             });
         }
     }
@@ -0,0 +1,325 @@
+//! Per-team battle statistics, shown in the overlay while fighting and as a summary once the
+//! match is decided.
+use std::time::Duration;
+
+use bevy::{prelude::*, time::common_conditions::on_timer, utils::HashMap};
+
+use crate::{
+    alliances::Alliances,
+    health::{DamageEvent, DamageSystems, ShipDestroyed},
+    lasers::Laser,
+    match_state::{MatchState, RestartMatch},
+    weapons::{threatens, Missile, WeaponFired},
+    Ship, Team,
+};
+
+/// Seconds of battle per column of the kills-over-time chart.
+const HISTORY_BUCKET: f32 = 10.0;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+pub fn plugin(app: &mut App) {
+    app.init_resource::<BattleStats>();
+    app.add_systems(
+        FixedUpdate,
+        (count_spawns, count_shots, count_hits, count_deaths).after(DamageSystems),
+    );
+    app.add_systems(FixedUpdate, reset_stats.run_if(on_event::<RestartMatch>));
+    app.add_systems(
+        Startup,
+        setup_stats_text.run_if(resource_exists::<Assets<Font>>),
+    );
+    app.add_systems(
+        Update,
+        (
+            update_stats_text.run_if(on_timer(Duration::from_secs(1))),
+            update_summary.run_if(state_changed::<MatchState>),
+        ),
+    );
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TeamStats {
+    /// Fighters launched.
+    pub spawned: u32,
+    /// Fighters lost.
+    pub destroyed: u32,
+    /// Ships destroyed by this team, including capital ships and turrets.
+    pub kills: u32,
+    pub shots_fired: u32,
+    pub hits: u32,
+    pub damage_dealt: f32,
+    /// Hostile missiles shot down.
+    pub interceptions: u32,
+}
+
+impl TeamStats {
+    /// Fraction of shots that hit something.
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            0.0
+        } else {
+            self.hits as f32 / self.shots_fired as f32
+        }
+    }
+}
+
+/// Statistics for the current match, cleared when it restarts.
+#[derive(Resource, Debug, Default)]
+pub struct BattleStats {
+    pub teams: HashMap<Team, TeamStats>,
+    /// Seconds into the match and the team that scored, for every kill.
+    pub kills: Vec<(f32, Team)>,
+    /// When the current match started, in fixed time.
+    started: f32,
+}
+
+impl BattleStats {
+    fn team(&mut self, team: Team) -> &mut TeamStats {
+        self.teams.entry(team).or_default()
+    }
+
+    /// Teams that have taken part in the match so far.
+    fn teams(&self) -> impl Iterator<Item = (Team, TeamStats)> + '_ {
+        Team::ALL
+            .into_iter()
+            .filter_map(|team| self.teams.get(&team).map(|stats| (team, *stats)))
+    }
+
+    /// A table with a row per team.
+    fn table(&self, alive: &HashMap<Team, usize>) -> String {
+        let mut table =
+            "Team    Alive Spawned  Lost Kills  Shots   Hits  Acc  Damage Intercepts\n".to_string();
+        for (team, stats) in self.teams() {
+            table.push_str(&format!(
+                "{:<7} {:>5} {:>7} {:>5} {:>5} {:>6} {:>6} {:>3.0}% {:>7.0} {:>10}\n",
+                format!("{team:?}"),
+                alive.get(&team).copied().unwrap_or_default(),
+                stats.spawned,
+                stats.destroyed,
+                stats.kills,
+                stats.shots_fired,
+                stats.hits,
+                stats.accuracy() * 100.0,
+                stats.damage_dealt,
+                stats.interceptions,
+            ));
+        }
+        table
+    }
+
+    /// Kills per team over time, one character per [`HISTORY_BUCKET`] seconds.
+    fn history(&self) -> String {
+        let Some(&(last, _)) = self.kills.last() else {
+            return String::new();
+        };
+        let buckets = (last / HISTORY_BUCKET) as usize + 1;
+        let mut counts: HashMap<Team, Vec<u32>> = HashMap::default();
+        for &(time, team) in &self.kills {
+            counts.entry(team).or_insert_with(|| vec![0; buckets])
+                [(time / HISTORY_BUCKET) as usize] += 1;
+        }
+        let most = counts.values().flatten().copied().max().unwrap_or(1);
+        let mut history = String::new();
+        for team in Team::ALL {
+            let Some(counts) = counts.get(&team) else {
+                continue;
+            };
+            let sparkline: String = counts
+                .iter()
+                .map(|&count| match count {
+                    0 => ' ',
+                    _ => SPARKS[(count * (SPARKS.len() as u32 - 1)).div_ceil(most) as usize],
+                })
+                .collect();
+            history.push_str(&format!("{:<7} {sparkline}\n", format!("{team:?}")));
+        }
+        history
+    }
+}
+
+fn reset_stats(mut stats: ResMut<BattleStats>, time: Res<Time<Fixed>>) {
+    *stats = BattleStats {
+        started: time.elapsed_secs(),
+        ..default()
+    };
+}
+
+fn count_spawns(mut stats: ResMut<BattleStats>, ships: Query<&Team, Added<Ship>>) {
+    for &team in ships.iter() {
+        stats.team(team).spawned += 1;
+    }
+}
+
+fn count_shots(mut stats: ResMut<BattleStats>, mut fired: EventReader<WeaponFired>) {
+    for team in fired.read().filter_map(|shot| shot.team) {
+        stats.team(team).shots_fired += 1;
+    }
+}
+
+fn count_hits(
+    mut stats: ResMut<BattleStats>,
+    mut damage_events: EventReader<DamageEvent>,
+    teams: Query<&Team>,
+    missiles: Query<(), With<Missile>>,
+    alliances: Res<Alliances>,
+) {
+    for event in damage_events.read() {
+        // Shooting down missiles is counted as interceptions, not as hits on the enemy.
+        if missiles.contains(event.target) {
+            continue;
+        }
+        let Some(Ok(&team)) = event.source.map(|source| teams.get(source)) else {
+            continue;
+        };
+        // Friendly fire isn't something to be credited for.
+        if teams
+            .get(event.target)
+            .is_ok_and(|&target| alliances.are_allied(team, target))
+        {
+            continue;
+        }
+        let stats = stats.team(team);
+        if !event.continuous && !event.splash {
+            stats.hits += 1;
+        }
+        stats.damage_dealt += event.amount;
+    }
+}
+
+fn count_deaths(
+    mut stats: ResMut<BattleStats>,
+    mut destroyed: EventReader<ShipDestroyed>,
+    victims: Query<(&Team, Has<Ship>)>,
+    missiles: Query<&Laser, With<Missile>>,
+    teams: Query<&Team>,
+    alliances: Res<Alliances>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs() - stats.started;
+    for event in destroyed.read() {
+        if let Ok(missile) = missiles.get(event.entity) {
+            if let Some(Ok(&killer)) = event.killer.map(|killer| teams.get(killer)) {
+                // Friendly fire can shoot down a team's own missiles, that's no interception.
+                if threatens(&alliances, missile.team, Some(killer)) {
+                    stats.team(killer).interceptions += 1;
+                }
+            }
+            continue;
+        }
+        // Asteroids have no team and don't count.
+        let Ok((&victim, is_fighter)) = victims.get(event.entity) else {
+            continue;
+        };
+        if is_fighter {
+            stats.team(victim).destroyed += 1;
+        }
+        if let Some(Ok(&killer)) = event.killer.map(|killer| teams.get(killer)) {
+            if alliances.are_allied(killer, victim) {
+                continue;
+            }
+            stats.team(killer).kills += 1;
+            stats.kills.push((now, killer));
+        }
+    }
+}
+
+fn alive(ships: &Query<&Team, With<Ship>>) -> HashMap<Team, usize> {
+    let mut alive = HashMap::default();
+    for &team in ships.iter() {
+        *alive.entry(team).or_default() += 1;
+    }
+    alive
+}
+
+#[derive(Component)]
+struct StatsText;
+
+#[derive(Component)]
+struct SummaryText;
+
+fn setup_stats_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("IBM Plex Mono/IBMPlexMono-Regular.ttf");
+    commands
+        .spawn(Node {
+            margin: UiRect {
+                left: Val::Px(24.0),
+                top: Val::Px(48.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_child((
+            Text::default(),
+            TextFont {
+                font: font.clone(),
+                font_size: 16.,
+                ..default()
+            },
+            StatsText,
+        ));
+    commands
+        .spawn(Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            top: Val::Px(96.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        })
+        .with_child((
+            Text::default(),
+            TextFont {
+                font,
+                font_size: 18.,
+                ..default()
+            },
+            SummaryText,
+        ));
+}
+
+fn update_stats_text(
+    mut text: Query<&mut Text, With<StatsText>>,
+    stats: Res<BattleStats>,
+    ships: Query<&Team, With<Ship>>,
+    lasers: Query<Entity, With<Laser>>,
+    entities: Query<Entity>,
+) {
+    let ship_count = ships.iter().len();
+    let laser_count = lasers.iter().len();
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let entity_count = entities.iter().len();
+    text.0 = format!(
+        "\
+        Entities: {entity_count}\n\
+        ├ Ships: {ship_count}\n\
+        └ Lasers: {laser_count}\n\
+        \n\
+        {}",
+        stats.table(&alive(&ships)),
+    );
+}
+
+/// Shows the final statistics once the match is decided, and logs them for headless runs.
+fn update_summary(
+    state: Res<State<MatchState>>,
+    stats: Res<BattleStats>,
+    ships: Query<&Team, With<Ship>>,
+    mut text: Query<&mut Text, With<SummaryText>>,
+) {
+    let summary = match state.get() {
+        MatchState::Victory(_) | MatchState::Draw => {
+            let summary = format!(
+                "{}\nKills over time\n{}",
+                stats.table(&alive(&ships)),
+                stats.history()
+            );
+            info!("Match summary\n{summary}");
+            summary
+        }
+        _ => String::new(),
+    };
+    if let Ok(mut text) = text.get_single_mut() {
+        text.0 = summary;
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_spatial::{kdtree::KDTree3A, SpatialAccess};
+
+use crate::{alliances::Alliances, health::Health, simulation::every, Ship, Team, TrackedByKDTree};
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<CurrentTarget>();
+    app.register_type::<SensorRange>();
+    app.add_systems(
+        FixedUpdate,
+        acquire_targets.run_if(every(Duration::from_secs_f32(0.25))),
+    );
+}
+
+/// The hostile a ship is currently engaging.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+#[reflect(Component)]
+pub struct CurrentTarget(pub Entity);
+
+/// How far away a ship can pick up hostiles.
+#[derive(Component, Reflect, Debug, Clone, Copy)]
+pub struct SensorRange(pub f32);
+
+impl Default for SensorRange {
+    fn default() -> Self {
+        Self(60.0)
+    }
+}
+
+/// Keeps every ship's [`CurrentTarget`] pointed at the nearest living hostile within sensor range.
+fn acquire_targets(
+    ships: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &Team,
+            &SensorRange,
+            Option<&CurrentTarget>,
+        ),
+        With<Ship>,
+    >,
+    candidates: Query<(&GlobalTransform, &Team, &Health)>,
+    tree: Res<KDTree3A<TrackedByKDTree>>,
+    alliances: Res<Alliances>,
+    mut commands: Commands,
+) {
+    // Not a `par_iter`: commands queued from several threads are applied in whatever order the
+    // threads finished, which would make battles play out differently between runs.
+    for (entity, transform, team, range, current) in ships.iter() {
+        let position = transform.translation();
+        let is_valid = |candidate: Entity| {
+            candidates
+                .get(candidate)
+                .is_ok_and(|(candidate_transform, candidate_team, health)| {
+                    !health.is_dead()
+                        && alliances.are_hostile(*team, *candidate_team)
+                        && candidate_transform.translation().distance(position) <= range.0
+                })
+        };
+        if current.is_some_and(|current| is_valid(current.0)) {
+            continue;
+        }
+        let nearest = tree
+            .within_distance(transform.translation_vec3a(), range.0)
+            .into_iter()
+            .filter_map(|(other_position, other)| {
+                other
+                    .filter(|&other| is_valid(other))
+                    .map(|other| (Vec3::from(other_position).distance_squared(position), other))
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b));
+        if let Some((_, target)) = nearest {
+            commands.entity(entity).try_insert(CurrentTarget(target));
+        } else if current.is_some() {
+            commands.entity(entity).remove::<CurrentTarget>();
+        }
+    }
+}
+
+/// Where to aim so a projectile fired now meets a target moving at a constant velocity.
+pub fn lead_position(
+    shooter: Vec3,
+    target: Vec3,
+    target_velocity: Vec3,
+    projectile_speed: f32,
+) -> Vec3 {
+    let time_to_hit = shooter.distance(target) / projectile_speed;
+    target + target_velocity * time_to_hit
+}
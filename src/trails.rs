@@ -0,0 +1,312 @@
+//! Engine glow and trails behind fighters, purely cosmetic.
+//!
+//! `T` cycles [`TrailQuality`]. Trails are only drawn for fighters near the camera, and that
+//! distance shrinks as the number of fighters grows past [`TrailSettings::budget`].
+//!
+//! Trails are ribbons facing the camera, rebuilt every frame into one mesh per team so
+//! thousands of them are still only a handful of draw calls.
+use std::collections::VecDeque;
+
+use bevy::{
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+        view::NoFrustumCulling,
+    },
+    utils::HashMap,
+};
+
+use crate::{ship_classes::ShipClass, Ship, Team};
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<TrailSettings>();
+    app.register_type::<TrailQuality>();
+    app.init_resource::<TrailSettings>();
+    app.add_systems(Startup, setup);
+    app.add_systems(
+        Update,
+        (
+            cycle_quality,
+            add_engines,
+            show_engines.run_if(resource_changed::<TrailSettings>),
+            (record_trails, build_ribbons).chain(),
+        ),
+    );
+}
+
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrailQuality {
+    /// No glow and no trails.
+    Off,
+    /// Engine glow and short trails.
+    #[default]
+    Low,
+    High,
+}
+
+impl TrailQuality {
+    /// Points kept per trail.
+    fn length(self) -> usize {
+        match self {
+            TrailQuality::Off => 0,
+            TrailQuality::Low => 12,
+            TrailQuality::High => 40,
+        }
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+pub struct TrailSettings {
+    pub quality: TrailQuality,
+    /// Fighters further than this from the camera have no trail.
+    pub lod_distance: f32,
+    /// Fighters alive before the trail distance starts shrinking.
+    pub budget: usize,
+    /// Distance a fighter travels between trail points.
+    pub spacing: f32,
+    /// Width of a trail at the engine, narrowing to nothing at its end.
+    pub width: f32,
+}
+
+impl Default for TrailSettings {
+    fn default() -> Self {
+        Self {
+            quality: TrailQuality::default(),
+            lod_distance: 150.0,
+            budget: 300,
+            spacing: 0.5,
+            width: 0.3,
+        }
+    }
+}
+
+/// Where a fighter's engine has been, newest first.
+#[derive(Component, Debug, Default)]
+struct Trail {
+    points: VecDeque<Vec3>,
+    /// Behind the ship's centre, in its local space.
+    engine: Vec3,
+}
+
+#[derive(Component)]
+struct EngineGlow;
+
+/// Holds the trails of every fighter in a team.
+#[derive(Component)]
+struct Ribbon(Team);
+
+#[derive(Resource)]
+struct EngineAssets {
+    mesh: Handle<Mesh>,
+    materials: HashMap<Team, Handle<StandardMaterial>>,
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // Colour and fade come from the vertices.
+    let ribbon_material = materials.add(StandardMaterial {
+        unlit: true,
+        alpha_mode: AlphaMode::Add,
+        cull_mode: None,
+        ..default()
+    });
+    for team in Team::ALL {
+        commands.spawn((
+            Ribbon(team),
+            Mesh3d(meshes.add(Mesh::new(
+                PrimitiveTopology::TriangleList,
+                RenderAssetUsages::default(),
+            ))),
+            MeshMaterial3d(ribbon_material.clone()),
+            Visibility::Hidden,
+            // The mesh moves with the fighters, so its bounds are never up to date.
+            NoFrustumCulling,
+            NotShadowCaster,
+        ));
+    }
+    let materials = Team::ALL
+        .into_iter()
+        .map(|team| {
+            let material = materials.add(StandardMaterial {
+                base_color: Color::BLACK,
+                emissive: LinearRgba::from(Color::from(team)) * 20.0,
+                unlit: true,
+                ..default()
+            });
+            (team, material)
+        })
+        .collect();
+    commands.insert_resource(EngineAssets {
+        mesh: meshes.add(Sphere::new(0.12)),
+        materials,
+    });
+}
+
+fn cycle_quality(mut settings: ResMut<TrailSettings>, keys: Res<ButtonInput<KeyCode>>) {
+    if keys.just_pressed(KeyCode::KeyT) {
+        settings.quality = match settings.quality {
+            TrailQuality::Off => TrailQuality::Low,
+            TrailQuality::Low => TrailQuality::High,
+            TrailQuality::High => TrailQuality::Off,
+        };
+    }
+}
+
+fn add_engines(
+    mut commands: Commands,
+    ships: Query<(Entity, &Team, &ShipClass), Added<Ship>>,
+    assets: Res<EngineAssets>,
+    settings: Res<TrailSettings>,
+) {
+    let visibility = if settings.quality == TrailQuality::Off {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    for (entity, team, class) in ships.iter() {
+        let engine = Vec3::new(0.0, 0.0, class.stats().collider_radius);
+        let Some(material) = assets.materials.get(team) else {
+            continue;
+        };
+        commands
+            .entity(entity)
+            .insert(Trail {
+                engine,
+                ..default()
+            })
+            .with_child((
+                EngineGlow,
+                Mesh3d(assets.mesh.clone()),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(engine),
+                visibility,
+            ));
+    }
+}
+
+fn show_engines(
+    mut engines: Query<&mut Visibility, With<EngineGlow>>,
+    settings: Res<TrailSettings>,
+) {
+    for mut visibility in engines.iter_mut() {
+        *visibility = if settings.quality == TrailQuality::Off {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+    }
+}
+
+/// How far from the camera trails are kept, pulled in when there are too many fighters.
+fn trail_distance(settings: &TrailSettings, fighters: usize) -> f32 {
+    let crowding = settings.budget as f32 / fighters.max(1) as f32;
+    settings.lod_distance * crowding.sqrt().min(1.0)
+}
+
+fn record_trails(
+    mut trails: Query<(&GlobalTransform, &mut Trail)>,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    settings: Res<TrailSettings>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let length = settings.quality.length();
+    let distance = trail_distance(&settings, trails.iter().len());
+    trails.par_iter_mut().for_each(|(transform, mut trail)| {
+        let engine = transform.transform_point(trail.engine);
+        if length == 0 || camera.translation().distance(engine) > distance {
+            trail.points.clear();
+            return;
+        }
+        if trail
+            .points
+            .front()
+            .is_none_or(|&last| last.distance(engine) >= settings.spacing)
+        {
+            trail.points.push_front(engine);
+        }
+        trail.points.truncate(length);
+    });
+}
+
+/// Vertices of the ribbons of one team.
+#[derive(Default)]
+struct RibbonBuffers {
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+}
+
+fn build_ribbons(
+    trails: Query<(&GlobalTransform, &Trail, &Team)>,
+    mut ribbons: Query<(&Ribbon, &Mesh3d, &mut Visibility)>,
+    cameras: Query<&GlobalTransform, With<Camera3d>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    settings: Res<TrailSettings>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    let camera = camera.translation();
+    let mut buffers: HashMap<Team, RibbonBuffers> = HashMap::default();
+    for (transform, trail, &team) in trails.iter() {
+        if trail.points.is_empty() {
+            continue;
+        }
+        let buffer = buffers.entry(team).or_default();
+        let color = LinearRgba::from(Color::from(team));
+        // Start at the engine itself so the trail doesn't lag a point behind the ship.
+        let engine = transform.transform_point(trail.engine);
+        let points: Vec<Vec3> = std::iter::once(engine)
+            .chain(trail.points.iter().copied())
+            .collect();
+        let last = points.len() - 1;
+        for (index, &point) in points.iter().enumerate() {
+            let fade = 1.0 - index as f32 / last as f32;
+            let along = points[(index + 1).min(last)] - points[index.saturating_sub(1)];
+            let side =
+                along.cross(camera - point).normalize_or_zero() * settings.width * fade * 0.5;
+            let vertex = buffer.positions.len() as u32;
+            buffer.positions.push((point - side).to_array());
+            buffer.positions.push((point + side).to_array());
+            let color = color.with_alpha(fade).to_f32_array();
+            buffer.colors.extend([color, color]);
+            if index > 0 {
+                buffer.indices.extend([
+                    vertex - 2,
+                    vertex - 1,
+                    vertex,
+                    vertex - 1,
+                    vertex + 1,
+                    vertex,
+                ]);
+            }
+        }
+    }
+    for (ribbon, mesh, mut visibility) in ribbons.iter_mut() {
+        let Some(buffer) = buffers.remove(&ribbon.0) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        let normals = vec![[0.0, 1.0, 0.0]; buffer.positions.len()];
+        meshes.insert(
+            &mesh.0,
+            Mesh::new(
+                PrimitiveTopology::TriangleList,
+                RenderAssetUsages::default(),
+            )
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, buffer.positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, buffer.colors)
+            .with_inserted_indices(Indices::U32(buffer.indices)),
+        );
+    }
+}
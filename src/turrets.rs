@@ -0,0 +1,226 @@
+use avian3d::prelude::LinearVelocity;
+use bevy::prelude::*;
+use bevy_spatial::{kdtree::KDTree3A, SpatialAccess};
+
+use crate::{
+    alliances::Alliances,
+    health::Health,
+    lasers::{Gun, Laser, ProjectileKind},
+    targeting::lead_position,
+    weapons::{threatens, Missile, Weapon},
+    Team, TrackedByKDTree,
+};
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<Turret>();
+    app.add_systems(FixedUpdate, track_targets);
+}
+
+/// A gun mounted on a capital ship that swivels to follow hostile fighters, or incoming missiles
+/// if it's a [point defense](Gun::point_defense) gun.
+///
+/// The turret rotates relative to `base`, its resting orientation on the hull, and can't turn
+/// further than its yaw and pitch limits.
+#[derive(Component, Reflect, Debug, Clone)]
+#[require(
+    Transform,
+    Visibility,
+    TrackedByKDTree,
+    Gun(turret_gun),
+    Health(|| Health::new(25.0))
+)]
+pub struct Turret {
+    pub base: Quat,
+    /// How far the turret can turn left or right of its base, in degrees.
+    pub yaw_limit: f32,
+    /// Lowest elevation in degrees, negative values point below the mount.
+    pub pitch_min: f32,
+    /// Highest elevation in degrees.
+    pub pitch_max: f32,
+    /// Degrees per second.
+    pub turn_rate: f32,
+    pub target: Option<Entity>,
+    /// Current rotation around the mount's up axis, in radians.
+    yaw: f32,
+    /// Current elevation, in radians.
+    pitch: f32,
+}
+
+impl Turret {
+    pub fn new(base: Quat) -> Self {
+        Self {
+            base,
+            yaw_limit: 170.0,
+            pitch_min: -5.0,
+            pitch_max: 85.0,
+            turn_rate: 90.0,
+            target: None,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+/// Hits the moment it fires, but slowly.
+pub fn turret_railgun() -> Gun {
+    Gun {
+        range: 90.0,
+        damage: 6.0,
+        arc: 2.0,
+        cooldown: 4.0,
+        burst: 1,
+        weapon: Weapon::Railgun,
+        projectile: ProjectileKind::Railgun,
+        ..default()
+    }
+}
+
+/// Burns whatever it's pointed at for a couple of seconds.
+pub fn turret_beam() -> Gun {
+    Gun {
+        range: 45.0,
+        damage: 3.0,
+        arc: 3.0,
+        cooldown: 5.0,
+        burst: 1,
+        weapon: Weapon::Beam { duration: 1.5 },
+        projectile: ProjectileKind::Beam,
+        ..default()
+    }
+}
+
+/// Picks off missiles, it only hits something as small if it's aimed right at it.
+pub fn turret_point_defense() -> Gun {
+    Gun {
+        range: 35.0,
+        damage: 1.0,
+        arc: 1.0,
+        cooldown: 0.4,
+        burst: 1,
+        weapon: Weapon::Railgun,
+        projectile: ProjectileKind::PointDefense,
+        point_defense: true,
+        ..default()
+    }
+}
+
+pub fn turret_gun() -> Gun {
+    Gun {
+        range: 60.0,
+        damage: 2.0,
+        arc: 4.0,
+        cooldown: 1.0,
+        burst: 3,
+        burst_interval: 0.1,
+        projectile: ProjectileKind::TurretLaser,
+        ..default()
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn track_targets(
+    mut turrets: Query<(
+        &mut Turret,
+        &mut Transform,
+        &GlobalTransform,
+        &Parent,
+        &Team,
+        &Gun,
+    )>,
+    mounts: Query<&GlobalTransform>,
+    candidates: Query<(&GlobalTransform, &Team, &Health, Option<&LinearVelocity>)>,
+    missiles: Query<(&GlobalTransform, &Laser, &Health), With<Missile>>,
+    tree: Res<KDTree3A<TrackedByKDTree>>,
+    alliances: Res<Alliances>,
+    time: Res<Time>,
+) {
+    turrets.par_iter_mut().for_each(
+        |(mut turret, mut transform, global_transform, parent, team, gun)| {
+            let position = global_transform.translation();
+            let is_valid = |candidate: Entity| {
+                candidates.get(candidate).is_ok_and(
+                    |(candidate_transform, candidate_team, health, _)| {
+                        !health.is_dead()
+                            && alliances.are_hostile(*team, *candidate_team)
+                            && candidate_transform.translation().distance(position) <= gun.range
+                    },
+                )
+            };
+            let is_incoming = |candidate: Entity| {
+                missiles
+                    .get(candidate)
+                    .is_ok_and(|(candidate_transform, missile, health)| {
+                        !health.is_dead()
+                            && threatens(&alliances, missile.team, Some(*team))
+                            && candidate_transform.translation().distance(position) <= gun.range
+                    })
+            };
+            let nearest = |wanted: &dyn Fn(Entity) -> bool| {
+                tree.within_distance(global_transform.translation_vec3a(), gun.range)
+                    .into_iter()
+                    .filter_map(|(other_position, other)| {
+                        other.filter(|&other| wanted(other)).map(|other| {
+                            (Vec3::from(other_position).distance_squared(position), other)
+                        })
+                    })
+                    .min_by(|(a, _), (b, _)| a.total_cmp(b))
+                    .map(|(_, other)| other)
+            };
+            // Point defense drops whatever ship it's tracking as soon as a missile comes in.
+            if !turret.target.is_some_and(&is_incoming) {
+                let incoming = if gun.point_defense {
+                    nearest(&is_incoming)
+                } else {
+                    None
+                };
+                let current = turret.target.filter(|&target| is_valid(target));
+                turret.target = incoming.or(current).or_else(|| nearest(&is_valid));
+            }
+            let Some((target_position, target_velocity)) = turret.target.and_then(|target| {
+                candidates
+                    .get(target)
+                    .map(|(transform, _, _, velocity)| {
+                        (
+                            transform.translation(),
+                            velocity.map_or(Vec3::ZERO, |velocity| velocity.0),
+                        )
+                    })
+                    .or_else(|_| {
+                        missiles.get(target).map(|(transform, missile, _)| {
+                            (transform.translation(), transform.forward() * missile.speed)
+                        })
+                    })
+                    .ok()
+            }) else {
+                return;
+            };
+            let Ok(mount) = mounts.get(parent.get()) else {
+                return;
+            };
+
+            let aim = lead_position(
+                position,
+                target_position,
+                target_velocity,
+                gun.weapon.projectile_speed(),
+            ) - position;
+            // Direction to aim in, relative to the turret's resting orientation.
+            let local = (mount.compute_transform().rotation * turret.base).inverse() * aim;
+            let desired_yaw = (-local.x).atan2(-local.z).clamp(
+                -turret.yaw_limit.to_radians(),
+                turret.yaw_limit.to_radians(),
+            );
+            let desired_pitch = local
+                .y
+                .atan2(local.xz().length())
+                .clamp(turret.pitch_min.to_radians(), turret.pitch_max.to_radians());
+
+            let step = turret.turn_rate.to_radians() * time.delta_secs();
+            turret.yaw += (desired_yaw - turret.yaw).clamp(-step, step);
+            turret.pitch += (desired_pitch - turret.pitch).clamp(-step, step);
+            transform.rotation = turret.base
+                * Quat::from_rotation_y(turret.yaw)
+                * Quat::from_rotation_x(turret.pitch);
+        },
+    );
+}
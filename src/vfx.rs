@@ -0,0 +1,263 @@
+//! Explosions where ships die and sparks where lasers hit. Effects are purely visual: they use
+//! their own randomness and never touch anything the simulation reads.
+//!
+//! Every effect of a kind shares one mesh and material so the renderer can batch them, and they
+//! animate by scale alone since fading would need a material per effect.
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::{thread_rng, Rng};
+
+use crate::{
+    asteroids::Asteroid,
+    capital_ships::CapitalShip,
+    health::{DamageEvent, DamageSystems, Health, ShipDestroyed},
+    lifetimes::DespawnAfter,
+    turrets::Turret,
+    weapons::Missile,
+};
+
+/// Chance of a spark each tick a beam burns something.
+const BEAM_SPARK_CHANCE: f64 = 0.2;
+
+pub fn plugin(app: &mut App) {
+    app.register_type::<Grow>();
+    app.register_type::<Tumble>();
+    app.add_systems(Startup, setup);
+    // Destroyed entities are still around until `FixedLast`, so they can be sized here.
+    app.add_systems(FixedUpdate, (explode, spark).after(DamageSystems));
+    app.add_systems(Update, (grow, tumble));
+}
+
+#[derive(Resource)]
+struct VfxAssets {
+    flash: (Handle<Mesh>, Handle<StandardMaterial>),
+    shockwave: (Handle<Mesh>, Handle<StandardMaterial>),
+    debris: (Handle<Mesh>, Handle<StandardMaterial>),
+    spark: (Handle<Mesh>, Handle<StandardMaterial>),
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let glowing = |materials: &mut Assets<StandardMaterial>, emissive: LinearRgba| {
+        materials.add(StandardMaterial {
+            base_color: Color::BLACK,
+            emissive,
+            unlit: true,
+            ..default()
+        })
+    };
+    commands.insert_resource(VfxAssets {
+        flash: (
+            meshes.add(
+                Sphere::new(1.0)
+                    .mesh()
+                    .ico(1)
+                    .expect("ico sphere subdivisions should be valid"),
+            ),
+            glowing(&mut materials, LinearRgba::rgb(40.0, 14.0, 3.0)),
+        ),
+        shockwave: (
+            meshes.add(Torus::new(0.95, 1.0)),
+            glowing(&mut materials, LinearRgba::rgb(6.0, 8.0, 14.0)),
+        ),
+        debris: (
+            meshes.add(Tetrahedron::default()),
+            materials.add(StandardMaterial {
+                base_color: Color::srgb(0.2, 0.2, 0.22),
+                emissive: LinearRgba::rgb(2.0, 0.6, 0.1),
+                perceptual_roughness: 0.8,
+                ..default()
+            }),
+        ),
+        spark: (
+            meshes.add(Cuboid::new(0.04, 0.04, 0.3)),
+            glowing(&mut materials, LinearRgba::rgb(30.0, 20.0, 4.0)),
+        ),
+    });
+}
+
+/// Scales an effect from `from` to `to` over `duration` seconds.
+#[derive(Component, Reflect, Debug, Clone)]
+struct Grow {
+    from: f32,
+    to: f32,
+    started: f64,
+    duration: f32,
+}
+
+/// Moves and spins an effect without involving physics.
+#[derive(Component, Reflect, Debug, Clone)]
+struct Tumble {
+    velocity: Vec3,
+    spin: Vec3,
+}
+
+/// How big the explosion is, and how many pieces fly off, for whatever was destroyed.
+fn blast_size(
+    entity: Entity,
+    destroyed: &Query<(
+        Has<CapitalShip>,
+        Has<Turret>,
+        Option<&Asteroid>,
+        Has<Missile>,
+    )>,
+) -> (f32, usize) {
+    match destroyed.get(entity) {
+        Ok((true, _, _, _)) => (14.0, 40),
+        Ok((_, true, _, _)) => (2.5, 6),
+        Ok((_, _, Some(asteroid), _)) => (asteroid.radius, 8),
+        Ok((_, _, _, true)) => (0.5, 0),
+        _ => (1.2, 6),
+    }
+}
+
+fn explode(
+    mut commands: Commands,
+    mut destroyed: EventReader<ShipDestroyed>,
+    sizes: Query<(
+        Has<CapitalShip>,
+        Has<Turret>,
+        Option<&Asteroid>,
+        Has<Missile>,
+    )>,
+    assets: Res<VfxAssets>,
+    time: Res<Time>,
+) {
+    let mut rng = thread_rng();
+    let now = time.elapsed_secs_f64();
+    for event in destroyed.read() {
+        let (size, pieces) = blast_size(event.entity, &sizes);
+        let (mesh, material) = &assets.flash;
+        commands.spawn((
+            Mesh3d(mesh.clone()),
+            MeshMaterial3d(material.clone()),
+            Transform::from_translation(event.position).with_scale(Vec3::splat(size)),
+            Grow {
+                from: size * 1.8,
+                to: size * 0.1,
+                started: now,
+                duration: 0.35,
+            },
+            DespawnAfter::new(Duration::from_secs_f32(0.35), &time),
+        ));
+        let (mesh, material) = &assets.shockwave;
+        let tilt = Quat::from_euler(
+            EulerRot::XYZ,
+            rng.gen_range(-0.6..0.6),
+            0.0,
+            rng.gen_range(-0.6..0.6),
+        );
+        commands.spawn((
+            Mesh3d(mesh.clone()),
+            MeshMaterial3d(material.clone()),
+            Transform::from_translation(event.position)
+                .with_rotation(tilt)
+                .with_scale(Vec3::splat(size)),
+            Grow {
+                from: size,
+                to: size * 6.0,
+                started: now,
+                duration: 0.6,
+            },
+            DespawnAfter::new(Duration::from_secs_f32(0.6), &time),
+        ));
+        let (mesh, material) = &assets.debris;
+        for _ in 0..pieces {
+            let direction = random_direction(&mut rng);
+            let scale = rng.gen_range(0.15..0.4) * size.sqrt();
+            let lifetime = rng.gen_range(1.5..3.0);
+            commands.spawn((
+                Mesh3d(mesh.clone()),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(event.position + direction * size * 0.5)
+                    .with_scale(Vec3::splat(scale)),
+                Tumble {
+                    velocity: direction * rng.gen_range(2.0..8.0) * size.sqrt(),
+                    spin: random_direction(&mut rng) * rng.gen_range(2.0..8.0),
+                },
+                Grow {
+                    from: scale,
+                    to: 0.0,
+                    started: now,
+                    duration: lifetime,
+                },
+                DespawnAfter::new(Duration::from_secs_f32(lifetime), &time),
+            ));
+        }
+    }
+}
+
+/// Sparks where a laser hit something that survived it.
+fn spark(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    health: Query<&Health>,
+    assets: Res<VfxAssets>,
+    time: Res<Time>,
+) {
+    let mut rng = thread_rng();
+    let now = time.elapsed_secs_f64();
+    let (mesh, material) = &assets.spark;
+    for event in damage_events.read() {
+        let Some(point) = event.point else {
+            continue;
+        };
+        if health.get(event.target).is_ok_and(Health::is_dead) {
+            continue;
+        }
+        // Beams burn every tick, so they only throw the odd spark.
+        let sparks = if event.continuous {
+            usize::from(rng.gen_bool(BEAM_SPARK_CHANCE))
+        } else {
+            4
+        };
+        for _ in 0..sparks {
+            let direction = random_direction(&mut rng);
+            commands.spawn((
+                Mesh3d(mesh.clone()),
+                MeshMaterial3d(material.clone()),
+                Transform::from_translation(point).looking_to(direction, Vec3::Y),
+                Tumble {
+                    velocity: direction * rng.gen_range(4.0..10.0),
+                    spin: Vec3::ZERO,
+                },
+                Grow {
+                    from: 1.0,
+                    to: 0.0,
+                    started: now,
+                    duration: 0.3,
+                },
+                DespawnAfter::new(Duration::from_secs_f32(0.3), &time),
+            ));
+        }
+    }
+}
+
+fn grow(mut effects: Query<(&mut Transform, &Grow)>, time: Res<Time>) {
+    let now = time.elapsed_secs_f64();
+    for (mut transform, grow) in effects.iter_mut() {
+        let t = ((now - grow.started) as f32 / grow.duration).clamp(0.0, 1.0);
+        transform.scale = Vec3::splat(grow.from + (grow.to - grow.from) * t);
+    }
+}
+
+fn tumble(mut effects: Query<(&mut Transform, &Tumble)>, time: Res<Time>) {
+    let dt = time.delta_secs();
+    for (mut transform, tumble) in effects.iter_mut() {
+        transform.translation += tumble.velocity * dt;
+        transform.rotate(Quat::from_scaled_axis(tumble.spin * dt));
+    }
+}
+
+fn random_direction(rng: &mut impl Rng) -> Vec3 {
+    Vec3::new(
+        rng.gen_range(-1.0..=1.0),
+        rng.gen_range(-1.0..=1.0),
+        rng.gen_range(-1.0..=1.0),
+    )
+    .normalize_or(Vec3::Y)
+}
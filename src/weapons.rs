@@ -0,0 +1,564 @@
+//! How guns fire, and how each kind of [`Weapon`] delivers its damage. Lasers, missiles and
+//! flak shells are [`Laser`] projectiles that fly and collide, railguns hit instantly and beams
+//! burn for a while. Everything ends up in [`Hits`], so friendly fire, knockback and
+//! [`DamageEvent`]s work the same for every weapon.
+//!
+//! Missiles can be shot down, so they have health, a collider and a place in the KD-tree where
+//! [point defense](Gun::point_defense) can find them.
+use std::time::Duration;
+
+use avian3d::prelude::{
+    Collider, ExternalImpulse, LayerMask, Sensor, SpatialQuery, SpatialQueryFilter,
+};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_spatial::{kdtree::KDTree3A, SpatialAccess};
+use serde::Deserialize;
+
+use crate::{
+    alliances::Alliances,
+    health::{DamageEvent, Health},
+    lasers::{FriendlyFire, Gun, Laser, LaserAssets, ProjectileKind, LASER_SPEED},
+    lifetimes::DespawnAfter,
+    match_state::MatchEntity,
+    ship_classes::Hardpoint,
+    targeting::CurrentTarget,
+    turrets::Turret,
+    Team, TrackedByKDTree,
+};
+
+/// Impulse applied to whatever is hit, per point of damage.
+const KNOCKBACK: f32 = 0.5;
+/// Size of the collider point defense has to hit.
+const MISSILE_RADIUS: f32 = 0.4;
+
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub enum Weapon {
+    /// Bolts flying straight ahead.
+    #[default]
+    Laser,
+    /// Homes in on the shooter's target, turning at most `turn_rate` degrees per second until
+    /// it runs out of fuel after `fuel` seconds.
+    Missile {
+        speed: f32,
+        turn_rate: f32,
+        fuel: f32,
+    },
+    /// Hits whatever is in front of it the moment it fires.
+    Railgun,
+    /// Shells that burst when they pass close to a hostile or reach the end of their range,
+    /// damaging everything within `radius`.
+    Flak { speed: f32, radius: f32 },
+    /// A continuous beam lasting `duration` seconds, the gun's damage is dealt per second.
+    Beam { duration: f32 },
+}
+
+impl Weapon {
+    /// How fast the projectile flies, for leading targets. Infinite for instant weapons.
+    pub fn projectile_speed(self) -> f32 {
+        match self {
+            Weapon::Laser => LASER_SPEED,
+            Weapon::Missile { speed, .. } | Weapon::Flak { speed, .. } => speed,
+            Weapon::Railgun | Weapon::Beam { .. } => f32::INFINITY,
+        }
+    }
+
+    /// Railgun tracers and beams are stretched to whatever they hit instead of flying.
+    pub fn stretches(self) -> bool {
+        matches!(self, Weapon::Railgun | Weapon::Beam { .. })
+    }
+}
+
+/// Sent for every shot fired, whatever the weapon.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct WeaponFired {
+    pub gun: Entity,
+    pub owner: Entity,
+    pub team: Option<Team>,
+    pub weapon: Weapon,
+}
+
+/// Steers a [`Laser`] towards `target`.
+#[derive(Component, Reflect, Debug, Clone)]
+#[require(
+    TrackedByKDTree,
+    Health(|| Health::new(0.5)),
+    Collider(|| Collider::sphere(MISSILE_RADIUS)),
+    Sensor
+)]
+pub struct Missile {
+    pub target: Option<Entity>,
+    /// Degrees per second.
+    pub turn_rate: f32,
+}
+
+/// Makes a [`Laser`] burst instead of hitting a single target.
+#[derive(Component, Reflect, Debug, Clone)]
+pub struct Flak {
+    pub radius: f32,
+    /// When the shell bursts if it hasn't come near anything.
+    pub fuse: f64,
+}
+
+/// A beam coming out of the [`Gun`] it's parented to, stretched to whatever it hits.
+#[derive(Component, Reflect, Debug, Clone)]
+#[require(MatchEntity)]
+pub struct Beam {
+    pub owner: Entity,
+    pub team: Option<Team>,
+    pub kind: ProjectileKind,
+    /// Whether the beam has touched anything yet. Only the first contact counts as a hit, the
+    /// rest is [continuous](DamageEvent::continuous) damage.
+    pub hit: bool,
+}
+
+/// The short-lived streak left by a railgun.
+#[derive(Component, Reflect, Debug, Clone)]
+#[require(MatchEntity)]
+pub struct Tracer {
+    pub team: Option<Team>,
+    pub kind: ProjectileKind,
+}
+
+/// Whether a missile fired by `missile_team` is a threat to `team`. Missiles and guns without a
+/// team treat everyone as hostile.
+pub fn threatens(alliances: &Alliances, missile_team: Option<Team>, team: Option<Team>) -> bool {
+    missile_team
+        .zip(team)
+        .is_none_or(|(missile_team, team)| alliances.are_hostile(missile_team, team))
+}
+
+/// How damage dealt by [`Hits`] came about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Contact {
+    Hit,
+    /// See [`DamageEvent::continuous`].
+    Burn,
+    /// See [`DamageEvent::splash`].
+    Splash,
+}
+
+/// Turns things shots run into into damage.
+#[derive(SystemParam)]
+pub struct Hits<'w, 's> {
+    spatial_query: SpatialQuery<'w, 's>,
+    parents: Query<'w, 's, &'static Parent>,
+    damageable: Query<'w, 's, Option<&'static Team>, With<Health>>,
+    impulses: Query<'w, 's, &'static mut ExternalImpulse>,
+    friendly_fire: Res<'w, FriendlyFire>,
+    alliances: Res<'w, Alliances>,
+    damage_events: EventWriter<'w, DamageEvent>,
+}
+
+impl Hits<'_, '_> {
+    /// Layers that shots fired by `team` collide with.
+    fn mask(&self, team: Option<Team>) -> LayerMask {
+        match (*self.friendly_fire, team) {
+            (FriendlyFire::Off, Some(team)) => !self.alliances.allied_layers(team),
+            _ => LayerMask::ALL,
+        }
+    }
+
+    /// The first collider a shot fired by `team` would run into, and how far away it is.
+    pub fn cast(
+        &self,
+        origin: Vec3,
+        direction: Dir3,
+        max_distance: f32,
+        team: Option<Team>,
+        excluded: impl IntoIterator<Item = Entity>,
+    ) -> Option<(Entity, f32)> {
+        self.spatial_query
+            .cast_ray(
+                origin,
+                direction,
+                max_distance,
+                true,
+                &SpatialQueryFilter::from_mask(self.mask(team)).with_excluded_entities(excluded),
+            )
+            .map(|hit| (hit.entity, hit.distance))
+    }
+
+    /// Colliders are often children (e.g. capital ship hull sections), so damage goes to the
+    /// closest ancestor that has health.
+    fn damageable(&self, collider: Entity) -> Option<Entity> {
+        std::iter::once(collider)
+            .chain(self.parents.iter_ancestors(collider))
+            .find(|&entity| self.damageable.contains(entity))
+    }
+
+    /// Damages whatever `collider` belongs to, pushing it along `direction`.
+    pub fn damage(
+        &mut self,
+        collider: Entity,
+        amount: f32,
+        owner: Entity,
+        team: Option<Team>,
+        point: Vec3,
+        direction: Vec3,
+    ) {
+        self.deal(
+            collider,
+            amount,
+            owner,
+            team,
+            point,
+            direction,
+            Contact::Hit,
+        );
+    }
+
+    /// Like [`Hits::damage`], for damage dealt a little every tick rather than in one go.
+    pub fn burn(
+        &mut self,
+        collider: Entity,
+        amount: f32,
+        owner: Entity,
+        team: Option<Team>,
+        point: Vec3,
+        direction: Vec3,
+    ) {
+        self.deal(
+            collider,
+            amount,
+            owner,
+            team,
+            point,
+            direction,
+            Contact::Burn,
+        );
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn deal(
+        &mut self,
+        collider: Entity,
+        amount: f32,
+        owner: Entity,
+        team: Option<Team>,
+        point: Vec3,
+        direction: Vec3,
+        contact: Contact,
+    ) {
+        let Some(target) = self.damageable(collider) else {
+            return;
+        };
+        let target_team = self.damageable.get(target).ok().flatten();
+        let allied = team
+            .zip(target_team)
+            .is_some_and(|(a, &b)| self.alliances.are_allied(a, b));
+        let amount = match *self.friendly_fire {
+            FriendlyFire::Reduced(fraction) if allied => amount * fraction,
+            _ => amount,
+        };
+        self.damage_events.send(DamageEvent {
+            target,
+            amount,
+            source: Some(owner),
+            point: Some(point),
+            continuous: contact == Contact::Burn,
+            splash: contact == Contact::Splash,
+        });
+        if let Ok(mut impulse) = self.impulses.get_mut(target) {
+            impulse.apply_impulse(direction.normalize_or_zero() * amount * KNOCKBACK);
+        }
+    }
+
+    /// Damages everything within `radius` of `point` once, however many colliders it has.
+    pub fn burst(
+        &mut self,
+        point: Vec3,
+        radius: f32,
+        amount: f32,
+        owner: Entity,
+        team: Option<Team>,
+    ) {
+        let colliders = self.spatial_query.shape_intersections(
+            &Collider::sphere(radius),
+            point,
+            Quat::IDENTITY,
+            &SpatialQueryFilter::from_mask(self.mask(team)).with_excluded_entities([owner]),
+        );
+        let mut targets = Vec::new();
+        for collider in colliders {
+            let Some(target) = self.damageable(collider) else {
+                continue;
+            };
+            if targets.contains(&target) {
+                continue;
+            }
+            let contact = if targets.is_empty() {
+                Contact::Hit
+            } else {
+                Contact::Splash
+            };
+            targets.push(target);
+            self.deal(collider, amount, owner, team, point, Vec3::ZERO, contact);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+/// Fires every gun that's ready and has something to shoot at.
+pub(crate) fn shoot(
+    mut commands: Commands,
+    mut guns: Query<(
+        Entity,
+        &GlobalTransform,
+        &mut Gun,
+        Option<&Parent>,
+        Has<Hardpoint>,
+        Option<&Turret>,
+    )>,
+    teams: Query<&Team>,
+    current_targets: Query<&CurrentTarget>,
+    candidates: Query<(&Team, &Health)>,
+    missiles: Query<&Laser, With<Missile>>,
+    tree: Res<KDTree3A<TrackedByKDTree>>,
+    spatial_query: SpatialQuery,
+    alliances: Res<Alliances>,
+    laser_assets: Option<Res<LaserAssets>>,
+    mut hits: Hits,
+    mut fired: EventWriter<WeaponFired>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs_f64();
+    for (gun_entity, transform, mut gun, parent, is_hardpoint, turret) in guns.iter_mut() {
+        if !gun.ready(now) {
+            continue;
+        }
+        // Shots from a hardpoint belong to the ship carrying it.
+        let owner = match parent {
+            Some(parent) if is_hardpoint => parent.get(),
+            _ => gun_entity,
+        };
+        let team = teams.get(owner).ok().copied();
+        if gun.burst_remaining > 0 {
+            gun.burst_remaining -= 1;
+        } else {
+            let is_hostile = |other: Entity| {
+                candidates.get(other).is_ok_and(|(other_team, health)| {
+                    !health.is_dead()
+                        && team.is_none_or(|team| alliances.are_hostile(team, *other_team))
+                })
+            };
+            let is_incoming = |other: Entity| {
+                gun.point_defense
+                    && missiles
+                        .get(other)
+                        .is_ok_and(|missile| threatens(&alliances, missile.team, team))
+            };
+            let target_in_arc = tree
+                .within_distance(transform.translation_vec3a(), gun.range)
+                .into_iter()
+                .any(|(position, other)| {
+                    other.is_some_and(|other| {
+                        other != owner && (is_hostile(other) || is_incoming(other))
+                    }) && gun.in_arc(transform, position.into())
+                });
+            // Capital ships aren't in the KD-tree, so look straight ahead for their hulls.
+            let hull_ahead = || {
+                let mask = team.map_or(LayerMask::ALL, |team| alliances.hostile_layers(team));
+                spatial_query
+                    .cast_ray(
+                        transform.translation(),
+                        transform.forward(),
+                        gun.range,
+                        true,
+                        &SpatialQueryFilter::from_mask(mask).with_excluded_entities([owner]),
+                    )
+                    .is_some()
+            };
+            if !target_in_arc && !hull_ahead() {
+                continue;
+            }
+            gun.burst_remaining = gun.burst.saturating_sub(1);
+        }
+        gun.last_fired = now;
+        fired.send(WeaponFired {
+            gun: gun_entity,
+            owner,
+            team,
+            weapon: gun.weapon,
+        });
+        let visuals = laser_assets.as_ref().and_then(|assets| {
+            if gun.weapon.stretches() {
+                assets.streak_visuals(gun.projectile, team)
+            } else {
+                assets.visuals(gun.projectile, team)
+            }
+        });
+        let speed = gun.weapon.projectile_speed();
+        let mut shot = match gun.weapon {
+            Weapon::Railgun => {
+                let origin = transform.translation();
+                let direction = transform.forward();
+                let hit = hits.cast(origin, direction, gun.range, team, [owner]);
+                let length = hit.map_or(gun.range, |(_, distance)| distance);
+                if let Some((collider, distance)) = hit {
+                    let point = origin + direction * distance;
+                    hits.damage(collider, gun.damage, owner, team, point, *direction);
+                }
+                commands.spawn((
+                    Tracer {
+                        team,
+                        kind: gun.projectile,
+                    },
+                    Transform::from_translation(origin + direction * length / 2.0)
+                        .looking_to(direction, Vec3::Y)
+                        .with_scale(Vec3::new(1.0, 1.0, length)),
+                    Visibility::default(),
+                    DespawnAfter::new(Duration::from_secs_f32(0.1), &time),
+                ))
+            }
+            Weapon::Beam { duration } => {
+                let mut beam = commands.spawn((
+                    Beam {
+                        owner,
+                        team,
+                        kind: gun.projectile,
+                        hit: false,
+                    },
+                    Transform::default(),
+                    Visibility::default(),
+                    DespawnAfter::new(Duration::from_secs_f32(duration), &time),
+                ));
+                beam.set_parent(gun_entity);
+                beam
+            }
+            Weapon::Laser | Weapon::Missile { .. } | Weapon::Flak { .. } => commands.spawn((
+                Laser {
+                    owner,
+                    team,
+                    damage: gun.damage,
+                    speed,
+                    kind: gun.projectile,
+                },
+                DespawnAfter::new(Duration::from_secs_f32(gun.range / speed), &time),
+                transform.compute_transform(),
+                *transform,
+                Visibility::default(),
+            )),
+        };
+        if let Some(visuals) = visuals {
+            shot.insert(visuals);
+        }
+        match gun.weapon {
+            Weapon::Missile {
+                turn_rate, fuel, ..
+            } => {
+                let target = current_targets
+                    .get(owner)
+                    .map(|target| target.0)
+                    .ok()
+                    .or(turret.and_then(|turret| turret.target));
+                shot.insert((
+                    Missile { target, turn_rate },
+                    DespawnAfter::new(Duration::from_secs_f32(fuel), &time),
+                ));
+                if let Some(team) = team {
+                    shot.insert(team.collision_layers());
+                }
+            }
+            Weapon::Flak { radius, .. } => {
+                shot.insert((
+                    Flak {
+                        radius,
+                        fuse: now + f64::from(gun.range / speed),
+                    },
+                    // Give the fuse time to go off first.
+                    DespawnAfter::new(Duration::from_secs_f32(gun.range / speed + 0.1), &time),
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Turns missiles towards their targets, as fast as their turn rate allows.
+pub(crate) fn steer_missiles(
+    mut missiles: Query<(&mut Transform, &Missile)>,
+    targets: Query<&GlobalTransform>,
+    time: Res<Time>,
+) {
+    missiles
+        .par_iter_mut()
+        .for_each(|(mut transform, missile)| {
+            let Some(target) = missile.target.and_then(|target| targets.get(target).ok()) else {
+                return;
+            };
+            let Ok(direction) = Dir3::new(target.translation() - transform.translation) else {
+                return;
+            };
+            let goal = Transform::default().looking_to(direction, Vec3::Y).rotation;
+            let max_angle = missile.turn_rate.to_radians() * time.delta_secs();
+            transform.rotation = transform.rotation.rotate_towards(goal, max_angle);
+        });
+}
+
+/// Bursts flak shells that come close to a hostile, or a missile threatening their side, or run
+/// out of fuse.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn detonate_flak(
+    mut commands: Commands,
+    shells: Query<(Entity, &GlobalTransform, &Laser, &Flak)>,
+    teams: Query<&Team>,
+    missiles: Query<&Laser, With<Missile>>,
+    tree: Res<KDTree3A<TrackedByKDTree>>,
+    alliances: Res<Alliances>,
+    mut hits: Hits,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs_f64();
+    for (entity, transform, laser, flak) in shells.iter() {
+        let position = transform.translation();
+        let close = now >= flak.fuse
+            || tree
+                .within_distance(transform.translation_vec3a(), flak.radius * 0.5)
+                .into_iter()
+                .filter_map(|(_, other)| other)
+                .any(|other| {
+                    if let Ok(missile) = missiles.get(other) {
+                        return threatens(&alliances, missile.team, laser.team);
+                    }
+                    teams.get(other).is_ok_and(|other| {
+                        laser
+                            .team
+                            .is_none_or(|team| alliances.are_hostile(team, *other))
+                    })
+                });
+        if close {
+            hits.burst(position, flak.radius, laser.damage, laser.owner, laser.team);
+            commands.entity(entity).try_despawn_recursive();
+        }
+    }
+}
+
+/// Stretches beams to whatever they hit and burns it.
+pub(crate) fn fire_beams(
+    mut beams: Query<(&mut Beam, &Parent, &mut Transform)>,
+    guns: Query<(&GlobalTransform, &Gun)>,
+    mut hits: Hits,
+    time: Res<Time>,
+) {
+    for (beam, parent, mut transform) in beams.iter_mut() {
+        let Ok((gun_transform, gun)) = guns.get(parent.get()) else {
+            continue;
+        };
+        let origin = gun_transform.translation();
+        let direction = gun_transform.forward();
+        let hit = hits.cast(origin, direction, gun.range, beam.team, [beam.owner]);
+        let length = hit.map_or(gun.range, |(_, distance)| distance);
+        if let Some((collider, distance)) = hit {
+            let amount = gun.damage * time.delta_secs();
+            let point = origin + direction * distance;
+            if beam.hit {
+                hits.burn(collider, amount, beam.owner, beam.team, point, *direction);
+            } else {
+                hits.damage(collider, amount, beam.owner, beam.team, point, *direction);
+                beam.hit = true;
+            }
+        }
+        *transform =
+            Transform::from_xyz(0.0, 0.0, -length / 2.0).with_scale(Vec3::new(1.0, 1.0, length));
+    }
+}